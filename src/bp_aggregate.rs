@@ -0,0 +1,101 @@
+use std::ops::Add;
+
+/// A summary of a run of entries, which a `BPTree` caches for every child of its
+/// index nodes so that a whole key range can be summarised in logarithmic time.
+///
+/// Summaries form a monoid: `combine` must be associative, and `identity` must
+/// leave any summary unchanged when combined with it on either side.
+pub trait Aggregate<K, V>: Clone {
+    /// The summary of no entries.
+    fn identity() -> Self;
+
+    /// The summary of a single entry.
+    fn from_entry(key: &K, value: &V) -> Self;
+
+    /// The summary of the entries of `self` followed by those of `other`.
+    fn combine(&self, other: &Self) -> Self;
+}
+
+/// No summary at all, which a `BPTree` keeps by default.
+impl<K, V> Aggregate<K, V> for () {
+    fn identity() -> Self {}
+
+    fn from_entry(_: &K, _: &V) -> Self {}
+
+    fn combine(&self, _: &Self) -> Self {}
+}
+
+/// Two summaries kept side by side.
+impl<K, V, A: Aggregate<K, V>, B: Aggregate<K, V>> Aggregate<K, V> for (A, B) {
+    fn identity() -> Self {
+        (A::identity(), B::identity())
+    }
+
+    fn from_entry(key: &K, value: &V) -> Self {
+        (A::from_entry(key, value), B::from_entry(key, value))
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        (self.0.combine(&other.0), self.1.combine(&other.1))
+    }
+}
+
+/// The sum of the values, starting from their `Default`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Sum<V>(pub V);
+
+impl<K, V: Clone + Default + Add<Output = V>> Aggregate<K, V> for Sum<V> {
+    fn identity() -> Self {
+        Sum(V::default())
+    }
+
+    fn from_entry(_: &K, value: &V) -> Self {
+        Sum(value.clone())
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Sum(self.0.clone() + other.0.clone())
+    }
+}
+
+/// The minimum of the values, if there are any.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Min<V>(pub Option<V>);
+
+impl<K, V: Clone + Ord> Aggregate<K, V> for Min<V> {
+    fn identity() -> Self {
+        Min(None)
+    }
+
+    fn from_entry(_: &K, value: &V) -> Self {
+        Min(Some(value.clone()))
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Min(Some(a.min(b).clone())),
+            (a, b) => Min(a.as_ref().or(b.as_ref()).cloned()),
+        }
+    }
+}
+
+/// The maximum of the values, if there are any.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Max<V>(pub Option<V>);
+
+impl<K, V: Clone + Ord> Aggregate<K, V> for Max<V> {
+    fn identity() -> Self {
+        Max(None)
+    }
+
+    fn from_entry(_: &K, value: &V) -> Self {
+        Max(Some(value.clone()))
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Max(Some(a.max(b).clone())),
+            (a, b) => Max(a.as_ref().or(b.as_ref()).cloned()),
+        }
+    }
+}
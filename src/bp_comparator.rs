@@ -0,0 +1,37 @@
+use std::cmp::Ordering;
+
+/// A total order on keys, used by a `BPTree` in place of `Ord`.
+///
+/// Lookups with a borrowed form `Q` of the key type need a comparator for `Q` as
+/// well, and it *must* order the borrowed forms the same way it orders the keys.
+///
+/// Closures of the form `Fn(&T, &T) -> Ordering` are comparators too.
+pub trait Comparator<T: ?Sized> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+/// The order given by `Ord`, which a `BPTree` uses by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct NaturalOrder;
+
+impl<T: Ord + ?Sized> Comparator<T> for NaturalOrder {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// The reverse of the order given by `Ord`, for a tree sorted in descending order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ReverseOrder;
+
+impl<T: Ord + ?Sized> Comparator<T> for ReverseOrder {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        b.cmp(a)
+    }
+}
+
+impl<T: ?Sized, F: Fn(&T, &T) -> Ordering> Comparator<T> for F {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
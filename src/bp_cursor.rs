@@ -0,0 +1,525 @@
+use std::fmt::Debug;
+use std::ops::Deref;
+use std::rc::Rc;
+
+use crate::bp_aggregate::Aggregate;
+use crate::bp_comparator::{Comparator, NaturalOrder};
+use crate::bp_tree::BPTree;
+use crate::node::{BPNode, BPNodePtr, BPPath};
+
+/// The error returned by [`CursorMut::insert_before`] and
+/// [`CursorMut::insert_after`] when the key would not fall strictly between the
+/// entries on either side of the insertion point.
+#[derive(Debug)]
+pub struct UnorderedKeyError<K, V> {
+    /// The key that was not inserted.
+    pub key: K,
+    /// The value that was not inserted.
+    pub value: V,
+}
+
+impl<K: Debug, V: Debug> std::fmt::Display for UnorderedKeyError<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "failed to insert {:?}, key {:?} is out of order at the cursor",
+            self.value, self.key,
+        )
+    }
+}
+
+impl<K: Debug, V: Debug> std::error::Error for UnorderedKeyError<K, V> {}
+
+/// The path down to a leaf, the leaf, and the index of an entry in it.
+type Position<const FANOUT: usize, K, V, A> = (BPPath<FANOUT, K, V, A>, BPNodePtr<FANOUT, K, V, A>, usize);
+
+/// The slot of an entry in a leaf, along with the path down to that leaf, or
+/// `None` for the ghost position past the last entry, which is also the one before
+/// the first.
+struct Slot<const FANOUT: usize, K: Clone, V, A> {
+    current: Option<Position<FANOUT, K, V, A>>,
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> Clone for Slot<FANOUT, K, V, A> {
+    fn clone(&self) -> Self {
+        Slot {
+            current: self.current.clone(),
+        }
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> Slot<FANOUT, K, V, A> {
+    /// The slot at `index` in `leaf`, or the first slot of a later leaf if `index`
+    /// is past the end of it.
+    fn at(path: BPPath<FANOUT, K, V, A>, leaf: BPNodePtr<FANOUT, K, V, A>, index: usize) -> Self {
+        let mut path = path;
+        let mut leaf = leaf;
+        let mut index = index;
+        while index >= leaf.borrow().as_leaf().len() {
+            match Self::next_leaf(&mut path) {
+                None => return Slot { current: None },
+                Some(next) => {
+                    leaf = next;
+                    index = 0;
+                }
+            }
+        }
+        Slot {
+            current: Some((path, leaf, index)),
+        }
+    }
+
+    /// Descends from `node` through the first (or, if `last` is true, the last)
+    /// children down to a leaf, pushing the index nodes on the way onto `path`.
+    fn descend(
+        path: &mut BPPath<FANOUT, K, V, A>,
+        node: BPNodePtr<FANOUT, K, V, A>,
+        last: bool,
+    ) -> BPNodePtr<FANOUT, K, V, A> {
+        let mut node = node;
+        loop {
+            let child = match node.borrow().deref() {
+                BPNode::Leaf(_) => None,
+                BPNode::Index(inode) => {
+                    let index = if last { inode.get_children().len() - 1 } else { 0 };
+                    Some((inode.get_child_clone(index).unwrap(), index))
+                }
+            };
+            match child {
+                None => return node,
+                Some((child, index)) => {
+                    path.push((std::mem::replace(&mut node, child), index, false));
+                }
+            }
+        }
+    }
+
+    /// Moves `path` over to the leaf after the one it leads to, and returns that
+    /// leaf, or `None` if it led to the last one.
+    fn next_leaf(path: &mut BPPath<FANOUT, K, V, A>) -> Option<BPNodePtr<FANOUT, K, V, A>> {
+        let child = loop {
+            let (node, child_index, exist) = path.last_mut()?;
+            let next = node.borrow().as_index().get_child_clone(*child_index + 1);
+            match next {
+                Some(child) => {
+                    *child_index += 1;
+                    *exist = false;
+                    break child;
+                }
+                None => {
+                    path.pop();
+                }
+            }
+        };
+        Some(Self::descend(path, child, false))
+    }
+
+    /// Moves `path` over to the leaf before the one it leads to, and returns that
+    /// leaf, or `None` if it led to the first one.
+    fn prev_leaf(path: &mut BPPath<FANOUT, K, V, A>) -> Option<BPNodePtr<FANOUT, K, V, A>> {
+        let child = loop {
+            let (node, child_index, exist) = path.last_mut()?;
+            match child_index.checked_sub(1) {
+                Some(index) => {
+                    *child_index = index;
+                    *exist = false;
+                    break node.borrow().as_index().get_child_clone(index).unwrap();
+                }
+                None => {
+                    path.pop();
+                }
+            }
+        };
+        Some(Self::descend(path, child, true))
+    }
+
+    fn first(root: &BPNodePtr<FANOUT, K, V, A>) -> Self {
+        let mut path = Vec::new();
+        let leaf = Self::descend(&mut path, root.clone(), false);
+        Self::at(path, leaf, 0)
+    }
+
+    fn last(root: &BPNodePtr<FANOUT, K, V, A>) -> Self {
+        let mut path = Vec::new();
+        let leaf = Self::descend(&mut path, root.clone(), true);
+        let len = leaf.borrow().as_leaf().len();
+        match len.checked_sub(1) {
+            Some(index) => Slot {
+                current: Some((path, leaf, index)),
+            },
+            None => Slot { current: None },
+        }
+    }
+
+    /// The slot of the first entry with a key greater than or equal to `key`.
+    fn seek<Q: ?Sized, C: Comparator<Q>>(root: &BPNodePtr<FANOUT, K, V, A>, key: &Q, cmp: &C) -> Self
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        let (path, leaf) = BPNode::search_path(root, key, cmp);
+        let (Ok(index) | Err(index)) = leaf.borrow().as_leaf().search_key(key, cmp);
+        Self::at(path, leaf, index)
+    }
+
+    /// Moves to the next slot, going from the ghost position to the first entry.
+    fn move_next(&mut self, root: &BPNodePtr<FANOUT, K, V, A>) {
+        *self = match self.current.take() {
+            None => Self::first(root),
+            Some((path, leaf, index)) => Self::at(path, leaf, index + 1),
+        };
+    }
+
+    /// Moves to the previous slot, going from the ghost position to the last entry.
+    fn move_prev(&mut self, root: &BPNodePtr<FANOUT, K, V, A>) {
+        *self = match self.current.take() {
+            None => Self::last(root),
+            Some((path, leaf, index)) if index > 0 => Slot {
+                current: Some((path, leaf, index - 1)),
+            },
+            Some((mut path, _, _)) => {
+                let current = Self::prev_leaf(&mut path).and_then(|prev| {
+                    let index = prev.borrow().as_leaf().len().checked_sub(1)?;
+                    Some((path, prev, index))
+                });
+                Slot { current }
+            }
+        };
+    }
+
+    /// Borrows the entry in the slot.
+    ///
+    /// # Safety
+    ///
+    /// No `&mut` to the leaf may exist for `'b`, as [`BPNode::deref_unguarded`]
+    /// requires: `'b` must not outlive a borrow of the tree that owns the slot,
+    /// shared or, if the tree is mutated through it, exclusive.
+    unsafe fn entry<'b>(&self) -> Option<(&'b K, &'b V)>
+    where
+        A: 'b,
+    {
+        let (_, leaf, index) = self.current.as_ref()?;
+        BPNode::deref_unguarded(leaf).as_leaf().get_key_value(*index)
+    }
+}
+
+/// A cursor over the entries of a `BPTree`, which can move back and forth and be
+/// repositioned by key.
+///
+/// The cursor is either on an entry or on a ghost position past the last entry,
+/// which is also the one before the first: moving forward from the last entry or
+/// back from the first one lands on it, and moving on from it wraps around.
+pub struct Cursor<'a, const FANOUT: usize, K: Clone, V, C = NaturalOrder, A = ()> {
+    tree: &'a BPTree<FANOUT, K, V, C, A>,
+    slot: Slot<FANOUT, K, V, A>,
+}
+
+impl<const FANOUT: usize, K: Clone, V, C, A: Aggregate<K, V>> Clone for Cursor<'_, FANOUT, K, V, C, A> {
+    fn clone(&self) -> Self {
+        Cursor {
+            tree: self.tree,
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, C: Comparator<K>, A: Aggregate<K, V>>
+    Cursor<'a, FANOUT, K, V, C, A>
+{
+    pub(crate) fn new(tree: &'a BPTree<FANOUT, K, V, C, A>) -> Self {
+        let slot = Slot::first(&tree.root);
+        Cursor { tree, slot }
+    }
+
+    /// Moves to the first entry with a key greater than or equal to `key`, or to
+    /// the ghost position if there is none.
+    pub fn seek<Q: ?Sized>(&mut self, key: &Q)
+    where
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.slot = Slot::seek(&self.tree.root, key, &self.tree.comparator);
+    }
+
+    /// Moves to the first entry, or to the ghost position if the tree is empty.
+    pub fn seek_to_first(&mut self) {
+        self.slot = Slot::first(&self.tree.root);
+    }
+
+    /// Moves to the last entry, or to the ghost position if the tree is empty.
+    pub fn seek_to_last(&mut self) {
+        self.slot = Slot::last(&self.tree.root);
+    }
+
+    /// Moves to the next entry, and returns it.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.slot.move_next(&self.tree.root);
+        self.key_value()
+    }
+
+    /// Moves to the previous entry, and returns it.
+    pub fn prev(&mut self) -> Option<(&'a K, &'a V)> {
+        self.slot.move_prev(&self.tree.root);
+        self.key_value()
+    }
+
+    /// Returns the key of the entry the cursor is on.
+    pub fn key(&self) -> Option<&'a K> {
+        self.key_value().map(|(key, _)| key)
+    }
+
+    /// Returns the value of the entry the cursor is on.
+    pub fn value(&self) -> Option<&'a V> {
+        self.key_value().map(|(_, value)| value)
+    }
+
+    /// Returns the entry the cursor is on.
+    pub fn key_value(&self) -> Option<(&'a K, &'a V)> {
+        // SAFETY: the cursor holds a shared borrow of the tree for 'a, so no `&mut`
+        // to the leaf can exist while the references are alive.
+        unsafe { self.slot.entry() }
+    }
+}
+
+/// A cursor over the entries of a `BPTree` that can also insert and remove
+/// entries where it stands.
+///
+/// It moves like a [`Cursor`], and, like an [`Entry`](crate::bp_entry::Entry),
+/// remembers the path it took down to its leaf. Edits are made in that leaf, and
+/// only the nodes on the path are fixed up, bottom-up. The cursor finds its place
+/// again by key only when a split or a merge moved the entries around it.
+pub struct CursorMut<'a, const FANOUT: usize, K: Clone, V, C = NaturalOrder, A = ()> {
+    tree: &'a mut BPTree<FANOUT, K, V, C, A>,
+    slot: Slot<FANOUT, K, V, A>,
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, C: Comparator<K>, A: Aggregate<K, V>>
+    CursorMut<'a, FANOUT, K, V, C, A>
+{
+    pub(crate) fn new(tree: &'a mut BPTree<FANOUT, K, V, C, A>) -> Self {
+        let slot = Slot::first(&tree.root);
+        CursorMut { tree, slot }
+    }
+
+    /// Moves to the first entry with a key greater than or equal to `key`, or to
+    /// the ghost position if there is none.
+    pub fn seek<Q: ?Sized>(&mut self, key: &Q)
+    where
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.slot = Slot::seek(&self.tree.root, key, &self.tree.comparator);
+    }
+
+    /// Moves to the first entry, or to the ghost position if the tree is empty.
+    pub fn seek_to_first(&mut self) {
+        self.slot = Slot::first(&self.tree.root);
+    }
+
+    /// Moves to the last entry, or to the ghost position if the tree is empty.
+    pub fn seek_to_last(&mut self) {
+        self.slot = Slot::last(&self.tree.root);
+    }
+
+    /// Moves to the next entry, and returns it.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<(&K, &V)> {
+        self.slot.move_next(&self.tree.root);
+        self.key_value()
+    }
+
+    /// Moves to the previous entry, and returns it.
+    pub fn prev(&mut self) -> Option<(&K, &V)> {
+        self.slot.move_prev(&self.tree.root);
+        self.key_value()
+    }
+
+    /// Returns the key of the entry the cursor is on.
+    pub fn key(&self) -> Option<&K> {
+        self.key_value().map(|(key, _)| key)
+    }
+
+    /// Returns the value of the entry the cursor is on.
+    pub fn value(&self) -> Option<&V> {
+        self.key_value().map(|(_, value)| value)
+    }
+
+    /// Returns the entry the cursor is on.
+    pub fn key_value(&self) -> Option<(&K, &V)> {
+        // SAFETY: the references borrow `self`, so neither the cursor nor anyone
+        // else can take a `&mut` to the leaf while they are alive.
+        unsafe { self.slot.entry() }
+    }
+
+    /// Inserts an entry right before the one the cursor is on, or after the last
+    /// entry if the cursor is on the ghost position. The cursor does not move.
+    ///
+    /// The key must be greater than that of the entry before the insertion point,
+    /// and less than that of the entry after it; otherwise the entry is handed
+    /// back in an error.
+    pub fn insert_before(&mut self, key: K, value: V) -> Result<(), UnorderedKeyError<K, V>> {
+        let mut before = self.slot.clone();
+        before.move_prev(&self.tree.root);
+        self.insert_between(before, self.slot.clone(), key, value)
+    }
+
+    /// Inserts an entry right after the one the cursor is on, or before the first
+    /// entry if the cursor is on the ghost position. The cursor does not move.
+    ///
+    /// The key must be greater than that of the entry before the insertion point,
+    /// and less than that of the entry after it; otherwise the entry is handed
+    /// back in an error.
+    pub fn insert_after(&mut self, key: K, value: V) -> Result<(), UnorderedKeyError<K, V>> {
+        let mut after = self.slot.clone();
+        after.move_next(&self.tree.root);
+        self.insert_between(self.slot.clone(), after, key, value)
+    }
+
+    fn insert_between(
+        &mut self,
+        before: Slot<FANOUT, K, V, A>,
+        after: Slot<FANOUT, K, V, A>,
+        key: K,
+        value: V,
+    ) -> Result<(), UnorderedKeyError<K, V>> {
+        let in_order = {
+            let cmp = &self.tree.comparator;
+            // SAFETY: the keys are dead before the tree is mutated, and until then
+            // no `&mut` to either leaf exists.
+            let (before, after) = unsafe { (before.entry(), after.entry()) };
+            before.is_none_or(|(before, _)| cmp.compare(before, &key).is_lt())
+                && after.is_none_or(|(after, _)| cmp.compare(&key, after).is_lt())
+        };
+        if !in_order {
+            return Err(UnorderedKeyError { key, value });
+        }
+        let (path, leaf, index) = self.insertion_point(before, after, &key);
+
+        // only a full leaf splits, which can move the entry the cursor is on
+        let reseek = leaf.borrow().as_leaf().is_maxinum().then(|| self.key().cloned());
+        leaf.borrow_mut()
+            .as_leaf_mut()
+            .insert_key_value(index, key, value);
+        for (node, child_index, _) in path.iter().rev() {
+            node.borrow_mut()
+                .as_index_mut()
+                .fix_overflow_child(*child_index);
+        }
+        self.tree.split_root();
+        self.tree.length += 1;
+
+        match reseek {
+            Some(current) => self.reseek(current),
+            None => {
+                if let Some((_, current, current_index)) = self.slot.current.as_mut() {
+                    if Rc::ptr_eq(current, &leaf) && *current_index >= index {
+                        *current_index += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The leaf a key going between the slots `before` and `after` is inserted
+    /// into, with the path down to it and the index it takes there.
+    fn insertion_point(
+        &self,
+        before: Slot<FANOUT, K, V, A>,
+        after: Slot<FANOUT, K, V, A>,
+        key: &K,
+    ) -> Position<FANOUT, K, V, A> {
+        match (before.current, after.current) {
+            (_, Some((path, leaf, index))) if index > 0 => (path, leaf, index),
+            (Some((path, leaf, index)), Some((after_path, after_leaf, _))) => {
+                // the slots are on either side of a leaf boundary, and the key goes
+                // to the side of the separator between the two leaves it falls on
+                let (node, child_index, _) = after_path
+                    .iter()
+                    .rev()
+                    .find(|(_, child_index, _)| *child_index > 0)
+                    .unwrap();
+                let before_separator = {
+                    let node = node.borrow();
+                    let separator = node.as_index().get_key(child_index - 1).unwrap();
+                    self.tree.comparator.compare(key, separator).is_lt()
+                };
+                if before_separator {
+                    (path, leaf, index + 1)
+                } else {
+                    (after_path, after_leaf, 0)
+                }
+            }
+            (None, Some((path, leaf, index))) => (path, leaf, index),
+            (Some((path, leaf, index)), None) => (path, leaf, index + 1),
+            (None, None) => (Vec::new(), self.tree.root.clone(), 0),
+        }
+    }
+
+    /// Removes the entry the cursor is on, and returns it. The cursor moves to
+    /// the next entry.
+    ///
+    /// Nodes on the remembered path are merged or rebalanced, bottom-up, only if
+    /// they underflow.
+    pub fn remove_current(&mut self) -> Option<(K, V)> {
+        let (mut path, leaf, index) = self.slot.current.take()?;
+        {
+            // the path was not searched by this key, so whether the key is also the
+            // separator before a child on the way down is worked out here
+            let node = leaf.borrow();
+            let key = node.as_leaf().get_key(index).unwrap();
+            for (node, child_index, exist) in path.iter_mut() {
+                *exist = *child_index > 0 && {
+                    let node = node.borrow();
+                    let separator = node.as_index().get_key(*child_index - 1).unwrap();
+                    self.tree.comparator.compare(separator, key).is_eq()
+                };
+            }
+        }
+
+        // only a leaf left underflowing is merged or rebalanced, which can move the
+        // entry after it
+        let underflow = !path.is_empty() && leaf.borrow().as_leaf().is_minimum();
+        let reseek = underflow.then(|| {
+            let next = Slot::at(path.clone(), leaf.clone(), index + 1);
+            // SAFETY: the key is cloned before the tree is mutated.
+            unsafe { next.entry() }.map(|(key, _)| key.clone())
+        });
+        let entry = leaf.borrow_mut().as_leaf_mut().remove(index).unwrap();
+        for (node, child_index, exist) in path.iter().rev() {
+            node.borrow_mut()
+                .as_index_mut()
+                .fix_underflow_child(*child_index, *exist);
+        }
+        self.tree.collapse_root();
+        self.tree.length -= 1;
+
+        match reseek {
+            Some(next) => self.reseek(next),
+            None => self.slot = Slot::at(path, leaf, index),
+        }
+        Some(entry)
+    }
+
+    /// Finds the place of the cursor again after the tree changed under it.
+    fn reseek(&mut self, key: Option<K>) {
+        self.slot = match key {
+            Some(key) => Slot::seek(&self.tree.root, &key, &self.tree.comparator),
+            None => Slot { current: None },
+        };
+    }
+}
+
+/// Mutable access to the values in place, which is only given out by trees that
+/// keep no aggregate, as the cached summaries could not follow the changes.
+impl<const FANOUT: usize, K: Clone, V, C: Comparator<K>> CursorMut<'_, FANOUT, K, V, C> {
+    /// Returns a mutable reference to the value of the entry the cursor is on.
+    pub fn value_mut(&mut self) -> Option<&mut V> {
+        let (_, leaf, index) = self.slot.current.as_ref()?;
+        // SAFETY: the reference borrows `self` exclusively, so the cursor derives
+        // no other reference from the leaf while it is alive.
+        unsafe { BPNode::deref_unguarded_mut(leaf) }
+            .as_leaf_mut()
+            .get_value_mut(*index)
+    }
+}
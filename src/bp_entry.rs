@@ -0,0 +1,328 @@
+use crate::bp_aggregate::Aggregate;
+use crate::bp_comparator::{Comparator, NaturalOrder};
+use crate::bp_tree::BPTree;
+use crate::node::{BPLeafNode, BPNode, BPNodePtr, BPPath};
+
+/// A view into a single entry of a `BPTree`, which may either be vacant or
+/// occupied.
+///
+/// This is constructed from the [`entry`](BPTree::entry) method on `BPTree`.
+/// The entry remembers the path it took down to its leaf, so inserting into or
+/// removing from it does not descend the tree again.
+pub enum Entry<'a, const FANOUT: usize, K: Clone, V, C = NaturalOrder, A = ()> {
+    Vacant(VacantEntry<'a, FANOUT, K, V, C, A>),
+    Occupied(OccupiedEntry<'a, FANOUT, K, V, C, A>),
+}
+
+/// A view into a vacant entry in a `BPTree`. It is part of the [`Entry`] enum.
+pub struct VacantEntry<'a, const FANOUT: usize, K: Clone, V, C = NaturalOrder, A = ()> {
+    key: K,
+    tree: &'a mut BPTree<FANOUT, K, V, C, A>,
+    path: BPPath<FANOUT, K, V, A>,
+    leaf: BPNodePtr<FANOUT, K, V, A>,
+    index: usize,
+}
+
+/// A view into an occupied entry in a `BPTree`. It is part of the [`Entry`] enum.
+pub struct OccupiedEntry<'a, const FANOUT: usize, K: Clone, V, C = NaturalOrder, A = ()> {
+    tree: &'a mut BPTree<FANOUT, K, V, C, A>,
+    path: BPPath<FANOUT, K, V, A>,
+    leaf: BPNodePtr<FANOUT, K, V, A>,
+    index: usize,
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, C: Comparator<K>, A: Aggregate<K, V>> Entry<'a, FANOUT, K, V, C, A> {
+    pub(crate) fn new(tree: &'a mut BPTree<FANOUT, K, V, C, A>, key: K) -> Self {
+        let (path, leaf) = BPNode::search_path(&tree.root, &key, &tree.comparator);
+        let search = leaf.borrow().as_leaf().search_key(&key, &tree.comparator);
+        match search {
+            Ok(index) => Entry::Occupied(OccupiedEntry {
+                tree,
+                path,
+                leaf,
+                index,
+            }),
+            Err(index) => Entry::Vacant(VacantEntry {
+                key,
+                tree,
+                path,
+                leaf,
+                index,
+            }),
+        }
+    }
+
+    /// Returns a reference to this entry's key.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Sets the value of the entry, and returns the occupied entry.
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, FANOUT, K, V, C, A> {
+        match self {
+            Entry::Occupied(mut entry) => {
+                entry.insert(value);
+                entry
+            }
+            Entry::Vacant(entry) => entry.insert_entry(value),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the default if empty, and
+    /// returns the occupied entry.
+    ///
+    /// Unlike [`or_insert`](Entry::or_insert) this is there for trees that keep an
+    /// aggregate too, as the entry refreshes the cached summaries on every change.
+    pub fn or_insert_entry(self, default: V) -> OccupiedEntry<'a, FANOUT, K, V, C, A> {
+        match self {
+            Entry::Occupied(entry) => entry,
+            Entry::Vacant(entry) => entry.insert_entry(default),
+        }
+    }
+
+    /// Provides in-place mutable access to an occupied entry before any potential
+    /// inserts into the tree.
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                entry.modify(f);
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, C: Comparator<K>, A: Aggregate<K, V>>
+    VacantEntry<'a, FANOUT, K, V, C, A>
+{
+    /// Gets a reference to the key that would be used when inserting a value
+    /// through the `VacantEntry`.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes ownership of the key.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Sets the value of the entry, and returns the occupied entry.
+    ///
+    /// Nodes on the remembered path are split, bottom-up, only if they overflow.
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, FANOUT, K, V, C, A> {
+        let VacantEntry {
+            key,
+            tree,
+            path,
+            leaf,
+            index,
+        } = self;
+
+        // only a full leaf splits, which can move the entry and the nodes above it
+        let moved = leaf.borrow().as_leaf().is_maxinum().then(|| key.clone());
+        leaf.borrow_mut()
+            .as_leaf_mut()
+            .insert_key_value(index, key, value);
+        for (node, child_index, _) in path.iter().rev() {
+            node.borrow_mut()
+                .as_index_mut()
+                .fix_overflow_child(*child_index);
+        }
+        tree.split_root();
+        tree.length += 1;
+
+        let (path, leaf, index) = match moved {
+            None => (path, leaf, index),
+            Some(key) => {
+                let (path, leaf) = BPNode::search_path(&tree.root, &key, &tree.comparator);
+                let index = leaf.borrow().as_leaf().search_key(&key, &tree.comparator).unwrap();
+                (path, leaf, index)
+            }
+        };
+        OccupiedEntry {
+            tree,
+            path,
+            leaf,
+            index,
+        }
+    }
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, C: Comparator<K>, A: Aggregate<K, V>>
+    OccupiedEntry<'a, FANOUT, K, V, C, A>
+{
+    /// The entry of the first (or, if `last` is true, the last) key in the tree.
+    pub(crate) fn edge(tree: &'a mut BPTree<FANOUT, K, V, C, A>, last: bool) -> Option<Self> {
+        let leaf = if last {
+            BPNode::rightmost_leaf(&tree.root)
+        } else {
+            BPNode::leftmost_leaf(&tree.root)
+        };
+        let (path, index) = {
+            let node = leaf.borrow();
+            let lnode = node.as_leaf();
+            let index = if last { lnode.len().checked_sub(1)? } else { 0 };
+            let key = lnode.get_key(index)?;
+            (BPNode::search_path(&tree.root, key, &tree.comparator).0, index)
+        };
+        Some(OccupiedEntry {
+            tree,
+            path,
+            leaf,
+            index,
+        })
+    }
+
+    fn leaf(&self) -> &BPLeafNode<FANOUT, K, V, A> {
+        // SAFETY: the entry holds the exclusive borrow of the tree, and `&self`
+        // keeps `leaf_mut` from being called while the result is alive.
+        unsafe { BPNode::deref_unguarded(&self.leaf) }.as_leaf()
+    }
+
+    fn leaf_mut(&mut self) -> &mut BPLeafNode<FANOUT, K, V, A> {
+        // SAFETY: the entry holds the exclusive borrow of the tree, and `&mut self`
+        // makes the result the only reference derived from the leaf.
+        unsafe { BPNode::deref_unguarded_mut(&self.leaf) }.as_leaf_mut()
+    }
+
+    /// Gets a reference to the key in the entry.
+    pub fn key(&self) -> &K {
+        self.leaf().get_key(self.index).unwrap()
+    }
+
+    /// Gets a reference to the value in the entry.
+    pub fn get(&self) -> &V {
+        self.leaf().get_value(self.index).unwrap()
+    }
+
+    /// Calls `f` on the value of the entry, and returns its result.
+    ///
+    /// The summaries cached on the remembered path are refreshed, bottom-up, once
+    /// `f` returns, so this works on trees that keep an aggregate too.
+    pub fn modify<R, F: FnOnce(&mut V) -> R>(&mut self, f: F) -> R {
+        let index = self.index;
+        let result = f(self.leaf_mut().get_value_mut(index).unwrap());
+        self.refresh_path();
+        result
+    }
+
+    /// Refreshes the summaries cached on the remembered path, bottom-up, after the
+    /// value of the entry changed.
+    fn refresh_path(&self) {
+        // the counts stay the same, and a summary without data can not go stale
+        if std::mem::size_of::<A>() == 0 {
+            return;
+        }
+        for (node, child_index, _) in self.path.iter().rev() {
+            node.borrow_mut().as_index_mut().refresh(*child_index);
+        }
+    }
+
+    /// Sets the value of the entry, and returns the entry's old value.
+    ///
+    /// The summaries cached on the remembered path are refreshed, bottom-up.
+    pub fn insert(&mut self, value: V) -> V {
+        let index = self.index;
+        let old_value = std::mem::replace(self.leaf_mut().get_value_mut(index).unwrap(), value);
+        self.refresh_path();
+        old_value
+    }
+
+    /// Takes the value of the entry out of the tree, and returns it.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Takes the key-value pair out of the tree, and returns it.
+    ///
+    /// Nodes on the remembered path are merged or rebalanced, bottom-up, only if
+    /// they underflow.
+    pub fn remove_entry(self) -> (K, V) {
+        let OccupiedEntry {
+            tree,
+            path,
+            leaf,
+            index,
+        } = self;
+
+        let entry = leaf.borrow_mut().as_leaf_mut().remove(index).unwrap();
+        for (node, child_index, exist) in path.iter().rev() {
+            node.borrow_mut()
+                .as_index_mut()
+                .fix_underflow_child(*child_index, *exist);
+        }
+        tree.collapse_root();
+        tree.length -= 1;
+        entry
+    }
+}
+
+/// Mutable access to the values in place, which is only given out by trees that
+/// keep no aggregate, as the cached summaries could not follow the changes.
+impl<'a, const FANOUT: usize, K: Clone, V, C: Comparator<K>> Entry<'a, FANOUT, K, V, C> {
+    /// Ensures a value is in the entry by inserting the default if empty, and
+    /// returns a mutable reference to the value in the entry.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the result of the default
+    /// function if empty, and returns a mutable reference to the value in the entry.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Like [`or_insert_with`](Entry::or_insert_with), but the default function
+    /// gets a reference to the key.
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting `V::default()` if empty, and
+    /// returns a mutable reference to the value in the entry.
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, C: Comparator<K>> VacantEntry<'a, FANOUT, K, V, C> {
+    /// Sets the value of the entry, and returns a mutable reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.insert_entry(value).into_mut()
+    }
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, C: Comparator<K>> OccupiedEntry<'a, FANOUT, K, V, C> {
+    /// Gets a mutable reference to the value in the entry.
+    pub fn get_mut(&mut self) -> &mut V {
+        let index = self.index;
+        self.leaf_mut().get_value_mut(index).unwrap()
+    }
+
+    /// Converts the entry into a mutable reference to its value.
+    pub fn into_mut(self) -> &'a mut V {
+        // SAFETY: the entry held the exclusive borrow of the tree for 'a, and is
+        // consumed, so the result is the only reference derived from the leaf.
+        let leaf = unsafe { BPNode::deref_unguarded_mut(&self.leaf) }.as_leaf_mut();
+        leaf.get_value_mut(self.index).unwrap()
+    }
+}
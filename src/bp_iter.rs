@@ -0,0 +1,718 @@
+use std::iter::{FusedIterator, Zip};
+use std::marker::PhantomData;
+use std::ops::{Bound, Deref, RangeBounds};
+use std::rc::Rc;
+use std::{slice, vec};
+
+use crate::bp_aggregate::Aggregate;
+use crate::bp_comparator::Comparator;
+use crate::bp_tree::BPTree;
+use crate::node::{BPLeafNode, BPNode, BPNodePtr};
+
+/// A position between two entries of the leaf chain: `index` is the slot of the
+/// entry right after it in `leaf`.
+struct LeafHandle<const FANOUT: usize, K: Clone, V, A> {
+    leaf: BPNodePtr<FANOUT, K, V, A>,
+    index: usize,
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> LeafHandle<FANOUT, K, V, A> {
+    fn first(root: &BPNodePtr<FANOUT, K, V, A>) -> Self {
+        LeafHandle {
+            leaf: BPNode::leftmost_leaf(root),
+            index: 0,
+        }
+    }
+
+    fn last(root: &BPNodePtr<FANOUT, K, V, A>) -> Self {
+        let leaf = BPNode::rightmost_leaf(root);
+        let index = leaf.borrow().as_leaf().len();
+        LeafHandle { leaf, index }
+    }
+
+    /// The position right before the first key greater than (or, if `inclusive`
+    /// is false, greater than or equal to) `key`.
+    fn seek<Q: ?Sized, C: Comparator<Q>>(
+        root: &BPNodePtr<FANOUT, K, V, A>,
+        key: &Q,
+        cmp: &C,
+        inclusive: bool,
+    ) -> Self
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        let leaf = BPNode::search_leaf(root, key, cmp);
+        let index = match leaf.borrow().as_leaf().search_key(key, cmp) {
+            Ok(index) if inclusive => index + 1,
+            Ok(index) => index,
+            Err(index) => index,
+        };
+        LeafHandle { leaf, index }
+    }
+
+    fn same_position(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.leaf, &other.leaf) && self.index == other.index
+    }
+}
+
+/// An iterator over a sub-range of the entries of a `BPTree`, in key order.
+///
+/// It walks the leaves from the front handle along their `next` links, and from
+/// the back handle along their `prev` links, until the two handles meet.
+pub struct Range<'a, const FANOUT: usize, K: Clone, V, A = ()> {
+    front: LeafHandle<FANOUT, K, V, A>,
+    back: LeafHandle<FANOUT, K, V, A>,
+    _marker: PhantomData<&'a (K, V, A)>,
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> Range<'a, FANOUT, K, V, A> {
+    pub(crate) fn new<Q, R, C>(tree: &'a BPTree<FANOUT, K, V, C, A>, range: R) -> Self
+    where
+        Q: ?Sized,
+        K: std::borrow::Borrow<Q>,
+        R: RangeBounds<Q>,
+        C: Comparator<Q>,
+    {
+        let cmp = &tree.comparator;
+        match (range.start_bound(), range.end_bound()) {
+            (Bound::Excluded(start), Bound::Excluded(end)) if cmp.compare(start, end).is_eq() => {
+                panic!("range start and end are equal and excluded in BPTree")
+            }
+            (
+                Bound::Included(start) | Bound::Excluded(start),
+                Bound::Included(end) | Bound::Excluded(end),
+            ) if cmp.compare(start, end).is_gt() => {
+                panic!("range start is greater than range end in BPTree")
+            }
+            _ => {}
+        }
+
+        let root = &tree.root;
+        let front = match range.start_bound() {
+            Bound::Included(start) => LeafHandle::seek(root, start, cmp, false),
+            Bound::Excluded(start) => LeafHandle::seek(root, start, cmp, true),
+            Bound::Unbounded => LeafHandle::first(root),
+        };
+        let back = match range.end_bound() {
+            Bound::Included(end) => LeafHandle::seek(root, end, cmp, true),
+            Bound::Excluded(end) => LeafHandle::seek(root, end, cmp, false),
+            Bound::Unbounded => LeafHandle::last(root),
+        };
+        Range {
+            front,
+            back,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Borrows the leaf `node` for as long as the iterator borrows the tree.
+    ///
+    /// # Safety
+    ///
+    /// `node` must be a leaf of the tree borrowed for `'a`, so that, as
+    /// [`BPNode::deref_unguarded`] requires, no `&mut` to it can exist for `'a`.
+    unsafe fn leaf(node: &BPNodePtr<FANOUT, K, V, A>) -> &'a BPLeafNode<FANOUT, K, V, A> {
+        BPNode::deref_unguarded(node).as_leaf()
+    }
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> Iterator
+    for Range<'a, FANOUT, K, V, A>
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.front.same_position(&self.back) {
+            // SAFETY: the handles only reach leaves of the tree borrowed for 'a.
+            let leaf = unsafe { Self::leaf(&self.front.leaf) };
+            if let Some(entry) = leaf.get_key_value(self.front.index) {
+                self.front.index += 1;
+                return Some(entry);
+            }
+            self.front = LeafHandle {
+                leaf: leaf.next.clone()?,
+                index: 0,
+            };
+        }
+        None
+    }
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> DoubleEndedIterator
+    for Range<'a, FANOUT, K, V, A>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while !self.front.same_position(&self.back) {
+            // SAFETY: the handles only reach leaves of the tree borrowed for 'a.
+            let leaf = unsafe { Self::leaf(&self.back.leaf) };
+            if self.back.index > 0 {
+                self.back.index -= 1;
+                return leaf.get_key_value(self.back.index);
+            }
+            let prev = leaf.prev.as_ref()?.upgrade()?;
+            let index = prev.borrow().as_leaf().len();
+            self.back = LeafHandle { leaf: prev, index };
+        }
+        None
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> FusedIterator
+    for Range<'_, FANOUT, K, V, A>
+{
+}
+
+/// An iterator over the entries of a `BPTree`, in key order.
+pub struct Iter<'a, const FANOUT: usize, K: Clone, V, A = ()> {
+    pub(crate) inner: Range<'a, FANOUT, K, V, A>,
+    pub(crate) length: usize,
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> Iterator
+    for Iter<'a, FANOUT, K, V, A>
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.inner.next()?;
+        self.length -= 1;
+        Some(entry)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.length, Some(self.length))
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> DoubleEndedIterator
+    for Iter<'_, FANOUT, K, V, A>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let entry = self.inner.next_back()?;
+        self.length -= 1;
+        Some(entry)
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> ExactSizeIterator
+    for Iter<'_, FANOUT, K, V, A>
+{
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> FusedIterator
+    for Iter<'_, FANOUT, K, V, A>
+{
+}
+
+/// An iterator over the keys of a `BPTree`, in order.
+pub struct Keys<'a, const FANOUT: usize, K: Clone, V, A = ()> {
+    pub(crate) inner: Iter<'a, FANOUT, K, V, A>,
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> Iterator
+    for Keys<'a, FANOUT, K, V, A>
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> ExactSizeIterator
+    for Keys<'_, FANOUT, K, V, A>
+{
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> DoubleEndedIterator
+    for Keys<'_, FANOUT, K, V, A>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> FusedIterator
+    for Keys<'_, FANOUT, K, V, A>
+{
+}
+
+/// An iterator over the values of a `BPTree`, in key order.
+pub struct Values<'a, const FANOUT: usize, K: Clone, V, A = ()> {
+    pub(crate) inner: Iter<'a, FANOUT, K, V, A>,
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> Iterator
+    for Values<'a, FANOUT, K, V, A>
+{
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> ExactSizeIterator
+    for Values<'_, FANOUT, K, V, A>
+{
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> DoubleEndedIterator
+    for Values<'_, FANOUT, K, V, A>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, value)| value)
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> FusedIterator
+    for Values<'_, FANOUT, K, V, A>
+{
+}
+
+type LeafChunkMut<'a, K, V> = Zip<slice::Iter<'a, K>, slice::IterMut<'a, V>>;
+
+/// A mutable iterator over the entries of a `BPTree`, in key order.
+///
+/// Every leaf is mutably borrowed exactly once, when either end first reaches
+/// it; once both ends are in the same leaf they share its chunk.
+pub struct IterMut<'a, const FANOUT: usize, K: Clone, V, A = ()> {
+    front: LeafChunkMut<'a, K, V>,
+    front_leaf: BPNodePtr<FANOUT, K, V, A>,
+    front_next: Option<BPNodePtr<FANOUT, K, V, A>>,
+    back: LeafChunkMut<'a, K, V>,
+    back_leaf: BPNodePtr<FANOUT, K, V, A>,
+    back_prev: Option<BPNodePtr<FANOUT, K, V, A>>,
+    met: bool,
+    length: usize,
+    _marker: PhantomData<&'a mut (K, V, A)>,
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> IterMut<'a, FANOUT, K, V, A> {
+    pub(crate) fn new<C>(tree: &'a mut BPTree<FANOUT, K, V, C, A>) -> Self {
+        let length = tree.length;
+        let first = BPNode::leftmost_leaf(&tree.root);
+        let last = BPNode::rightmost_leaf(&tree.root);
+        // SAFETY: the tree is borrowed for 'a, and the last leaf is only loaded
+        // when it is not also the first.
+        let (front, front_next, _) = unsafe { Self::load(&first) };
+        let met = Rc::ptr_eq(&first, &last);
+        let (back, _, back_prev) = if met {
+            (Self::empty_chunk(), None, None)
+        } else {
+            unsafe { Self::load(&last) }
+        };
+        IterMut {
+            front,
+            front_leaf: first,
+            front_next,
+            back,
+            back_leaf: last,
+            back_prev,
+            met,
+            length,
+            _marker: PhantomData,
+        }
+    }
+
+    fn empty_chunk() -> LeafChunkMut<'a, K, V> {
+        [].iter().zip([].iter_mut())
+    }
+
+    /// Mutably borrows the entries of `node`, along with its sibling links.
+    ///
+    /// # Safety
+    ///
+    /// `node` must be a leaf of the tree exclusively borrowed for `'a`, and must
+    /// not have been loaded before, so that, as [`BPNode::deref_unguarded_mut`]
+    /// requires, no other reference to it can exist for `'a`.
+    #[allow(clippy::type_complexity)]
+    unsafe fn load(
+        node: &BPNodePtr<FANOUT, K, V, A>,
+    ) -> (
+        LeafChunkMut<'a, K, V>,
+        Option<BPNodePtr<FANOUT, K, V, A>>,
+        Option<BPNodePtr<FANOUT, K, V, A>>,
+    ) {
+        let leaf = BPNode::deref_unguarded_mut(node).as_leaf_mut();
+        let next = leaf.next.clone();
+        let prev = leaf.prev.as_ref().and_then(|prev| prev.upgrade());
+        let (keys, values) = leaf.entries_mut();
+        (keys.iter().zip(values.iter_mut()), next, prev)
+    }
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> Iterator
+    for IterMut<'a, FANOUT, K, V, A>
+{
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.front.next() {
+                self.length -= 1;
+                return Some(entry);
+            }
+            if self.met {
+                return None;
+            }
+            let next = self.front_next.take()?;
+            if Rc::ptr_eq(&next, &self.back_leaf) {
+                self.met = true;
+                self.front = std::mem::replace(&mut self.back, Self::empty_chunk());
+            } else {
+                // SAFETY: the front only moves on to leaves the back has not
+                // reached, which neither end has loaded.
+                let (front, front_next, _) = unsafe { Self::load(&next) };
+                self.front = front;
+                self.front_next = front_next;
+            }
+            self.front_leaf = next;
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.length, Some(self.length))
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> ExactSizeIterator
+    for IterMut<'_, FANOUT, K, V, A>
+{
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> DoubleEndedIterator
+    for IterMut<'_, FANOUT, K, V, A>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.met {
+                let entry = self.front.next_back()?;
+                self.length -= 1;
+                return Some(entry);
+            }
+            if let Some(entry) = self.back.next_back() {
+                self.length -= 1;
+                return Some(entry);
+            }
+            let prev = self.back_prev.take()?;
+            if Rc::ptr_eq(&prev, &self.front_leaf) {
+                self.met = true;
+            } else {
+                // SAFETY: the back only moves on to leaves the front has not
+                // reached, which neither end has loaded.
+                let (back, _, back_prev) = unsafe { Self::load(&prev) };
+                self.back = back;
+                self.back_prev = back_prev;
+            }
+            self.back_leaf = prev;
+        }
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> FusedIterator
+    for IterMut<'_, FANOUT, K, V, A>
+{
+}
+
+/// A mutable iterator over the values of a `BPTree`, in key order.
+pub struct ValuesMut<'a, const FANOUT: usize, K: Clone, V, A = ()> {
+    pub(crate) inner: IterMut<'a, FANOUT, K, V, A>,
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> Iterator
+    for ValuesMut<'a, FANOUT, K, V, A>
+{
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> ExactSizeIterator
+    for ValuesMut<'_, FANOUT, K, V, A>
+{
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> DoubleEndedIterator
+    for ValuesMut<'_, FANOUT, K, V, A>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, value)| value)
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> FusedIterator
+    for ValuesMut<'_, FANOUT, K, V, A>
+{
+}
+
+type LeafChunk<K, V> = Zip<vec::IntoIter<K>, vec::IntoIter<V>>;
+
+/// An owning iterator over the entries of a `BPTree`, in key order.
+///
+/// The entries of each leaf are moved out when either end first reaches it,
+/// like [`IterMut`] does with its borrows.
+pub struct IntoIter<const FANOUT: usize, K: Clone, V, A = ()> {
+    front: LeafChunk<K, V>,
+    front_leaf: BPNodePtr<FANOUT, K, V, A>,
+    front_next: Option<BPNodePtr<FANOUT, K, V, A>>,
+    back: LeafChunk<K, V>,
+    back_leaf: BPNodePtr<FANOUT, K, V, A>,
+    back_prev: Option<BPNodePtr<FANOUT, K, V, A>>,
+    met: bool,
+    length: usize,
+    // keeps every node alive, so the `prev` links can still be upgraded
+    _root: BPNodePtr<FANOUT, K, V, A>,
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> IntoIter<FANOUT, K, V, A> {
+    pub(crate) fn new<C>(tree: BPTree<FANOUT, K, V, C, A>) -> Self {
+        let first = BPNode::leftmost_leaf(&tree.root);
+        let last = BPNode::rightmost_leaf(&tree.root);
+        let (front, front_next, _) = Self::load(&first);
+        let met = Rc::ptr_eq(&first, &last);
+        let (back, _, back_prev) = if met {
+            (Self::empty_chunk(), None, None)
+        } else {
+            Self::load(&last)
+        };
+        IntoIter {
+            front,
+            front_leaf: first,
+            front_next,
+            back,
+            back_leaf: last,
+            back_prev,
+            met,
+            length: tree.length,
+            _root: tree.root,
+        }
+    }
+
+    fn empty_chunk() -> LeafChunk<K, V> {
+        Vec::new().into_iter().zip(Vec::new())
+    }
+
+    /// Moves the entries out of `node`, along with its sibling links.
+    #[allow(clippy::type_complexity)]
+    fn load(
+        node: &BPNodePtr<FANOUT, K, V, A>,
+    ) -> (
+        LeafChunk<K, V>,
+        Option<BPNodePtr<FANOUT, K, V, A>>,
+        Option<BPNodePtr<FANOUT, K, V, A>>,
+    ) {
+        let mut node = node.borrow_mut();
+        let leaf = node.as_leaf_mut();
+        let next = leaf.next.clone();
+        let prev = leaf.prev.as_ref().and_then(|prev| prev.upgrade());
+        let (keys, values) = leaf.take_entries();
+        (keys.into_iter().zip(values), next, prev)
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> Iterator for IntoIter<FANOUT, K, V, A> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.front.next() {
+                self.length -= 1;
+                return Some(entry);
+            }
+            if self.met {
+                return None;
+            }
+            let next = self.front_next.take()?;
+            if Rc::ptr_eq(&next, &self.back_leaf) {
+                self.met = true;
+                self.front = std::mem::replace(&mut self.back, Self::empty_chunk());
+            } else {
+                let (front, front_next, _) = Self::load(&next);
+                self.front = front;
+                self.front_next = front_next;
+            }
+            self.front_leaf = next;
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.length, Some(self.length))
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> ExactSizeIterator for IntoIter<FANOUT, K, V, A> {}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> DoubleEndedIterator for IntoIter<FANOUT, K, V, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.met {
+                let entry = self.front.next_back()?;
+                self.length -= 1;
+                return Some(entry);
+            }
+            if let Some(entry) = self.back.next_back() {
+                self.length -= 1;
+                return Some(entry);
+            }
+            let prev = self.back_prev.take()?;
+            if Rc::ptr_eq(&prev, &self.front_leaf) {
+                self.met = true;
+            } else {
+                let (back, _, back_prev) = Self::load(&prev);
+                self.back = back;
+                self.back_prev = back_prev;
+            }
+            self.back_leaf = prev;
+        }
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> FusedIterator for IntoIter<FANOUT, K, V, A> {}
+
+/// An iterator that removes and yields the entries of a `BPTree` matching a
+/// predicate, in key order.
+///
+/// It walks the leaves in order taking the matching entries out of them. When it
+/// is dropped, it merges or rebalances the leaves left underflowing, and
+/// refreshes the counts and summaries cached above them, as the predicate may
+/// also have changed values. Only the leaves it reached are repaired: the rest
+/// of the tree is cut off in front of the first entry it did not visit, and
+/// joined back afterwards.
+///
+/// The nodes are detached from the tree while the iterator lives, and only put
+/// back once they are repaired, so leaking the iterator (with `mem::forget`, say)
+/// leaves the tree empty rather than broken.
+pub struct ExtractIf<'a, const FANOUT: usize, K: Clone, V, C, F, A = ()>
+where
+    A: Aggregate<K, V>,
+    F: FnMut(&K, &mut V) -> bool,
+{
+    tree: &'a mut BPTree<FANOUT, K, V, C, A>,
+    /// The root and the length taken out of `tree`, which is left empty.
+    root: BPNodePtr<FANOUT, K, V, A>,
+    length: usize,
+    /// The index nodes down to `leaf`, with the index of the child taken in each.
+    path: Vec<(BPNodePtr<FANOUT, K, V, A>, usize)>,
+    leaf: Option<BPNodePtr<FANOUT, K, V, A>>,
+    index: usize,
+    pred: F,
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, C, F, A: Aggregate<K, V>> ExtractIf<'a, FANOUT, K, V, C, F, A>
+where
+    F: FnMut(&K, &mut V) -> bool,
+{
+    pub(crate) fn new(tree: &'a mut BPTree<FANOUT, K, V, C, A>, pred: F) -> Self {
+        let root = std::mem::replace(&mut tree.root, BPNode::new_leaf_ptr());
+        let length = std::mem::take(&mut tree.length);
+        let mut path = Vec::new();
+        let leaf = Self::descend(&mut path, root.clone());
+        ExtractIf {
+            tree,
+            root,
+            length,
+            path,
+            leaf: Some(leaf),
+            index: 0,
+            pred,
+        }
+    }
+
+    /// Goes down the first children from `node` to a leaf, adding the index nodes
+    /// on the way to `path`.
+    fn descend(
+        path: &mut Vec<(BPNodePtr<FANOUT, K, V, A>, usize)>,
+        mut node: BPNodePtr<FANOUT, K, V, A>,
+    ) -> BPNodePtr<FANOUT, K, V, A> {
+        loop {
+            let child = match node.borrow().deref() {
+                BPNode::Leaf(_) => None,
+                BPNode::Index(index) => index.get_child_clone(0),
+            };
+            match child {
+                None => return node,
+                Some(child) => path.push((std::mem::replace(&mut node, child), 0)),
+            }
+        }
+    }
+
+    /// Moves `path` on to the leaf after the current one, and returns that leaf.
+    fn next_leaf(&mut self) -> Option<BPNodePtr<FANOUT, K, V, A>> {
+        while let Some((node, child_index)) = self.path.pop() {
+            let next = node.borrow().as_index().get_child_clone(child_index + 1);
+            if let Some(next) = next {
+                self.path.push((node, child_index + 1));
+                return Some(Self::descend(&mut self.path, next));
+            }
+        }
+        None
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, C, F, A: Aggregate<K, V>> Iterator for ExtractIf<'_, FANOUT, K, V, C, F, A>
+where
+    F: FnMut(&K, &mut V) -> bool,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.leaf.clone()?;
+            let mut node = node.borrow_mut();
+            let leaf = node.as_leaf_mut();
+            if self.index == leaf.len() {
+                drop(node);
+                self.leaf = self.next_leaf();
+                self.index = 0;
+                continue;
+            }
+            let (keys, values) = leaf.entries_mut();
+            if (self.pred)(&keys[self.index], &mut values[self.index]) {
+                self.length -= 1;
+                return leaf.remove(self.index);
+            }
+            self.index += 1;
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.length))
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, C, F, A: Aggregate<K, V>> FusedIterator for ExtractIf<'_, FANOUT, K, V, C, F, A> where
+    F: FnMut(&K, &mut V) -> bool
+{
+}
+
+impl<const FANOUT: usize, K: Clone, V, C, F, A: Aggregate<K, V>> Drop for ExtractIf<'_, FANOUT, K, V, C, F, A>
+where
+    F: FnMut(&K, &mut V) -> bool,
+{
+    fn drop(&mut self) {
+        std::mem::swap(&mut self.tree.root, &mut self.root);
+        self.tree.length = self.length;
+        match self.leaf {
+            Some(_) => {
+                let path: Vec<usize> = self.path.iter().map(|(_, child_index)| *child_index).collect();
+                self.tree.repair_prefix(&path, self.index);
+            }
+            None => self.tree.repair_all(),
+        }
+    }
+}
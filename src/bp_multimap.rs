@@ -0,0 +1,191 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::iter::FusedIterator;
+use std::ops::Bound;
+
+use crate::bp_comparator::{Comparator, NaturalOrder};
+use crate::bp_iter::{self, Iter as TreeIter};
+use crate::bp_tree::BPTree;
+
+/// A key of the underlying tree: the user's key, tagged with the sequence number
+/// of its insertion so that equal keys still have distinct, ordered entries.
+#[derive(Clone)]
+struct MultiKey<K> {
+    key: K,
+    seq: u64,
+}
+
+/// Orders `MultiKey`s by their key under `C`, and equal keys by insertion.
+struct MultiKeyOrder<C>(C);
+
+impl<K, C: Comparator<K>> Comparator<MultiKey<K>> for MultiKeyOrder<C> {
+    fn compare(&self, a: &MultiKey<K>, b: &MultiKey<K>) -> Ordering {
+        self.0.compare(&a.key, &b.key).then(a.seq.cmp(&b.seq))
+    }
+}
+
+/// An ordered multimap based on a `BPTree`, where a key may map to many values.
+///
+/// Values under equal keys are kept in insertion order, and may span any number
+/// of leaves. Every entry is stored under its key and a sequence number, so the
+/// separators of the underlying tree stay exact even with duplicate keys.
+pub struct BPMultiMap<const FANOUT: usize, K: Clone, V, C = NaturalOrder> {
+    map: BPTree<FANOUT, MultiKey<K>, V, MultiKeyOrder<C>>,
+    next_seq: u64,
+}
+
+impl<const FANOUT: usize, K: Clone + Debug, V: Debug, C: Comparator<K>> Debug
+    for BPMultiMap<FANOUT, K, V, C>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, C: Default> Default for BPMultiMap<FANOUT, K, V, C> {
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<const FANOUT: usize, K: Clone + Ord, V> BPMultiMap<FANOUT, K, V> {
+    pub fn new() -> Self {
+        Self::with_comparator(NaturalOrder)
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, C> BPMultiMap<FANOUT, K, V, C> {
+    /// Makes a new, empty multimap whose keys are ordered by `comparator`.
+    pub fn with_comparator(comparator: C) -> Self {
+        BPMultiMap {
+            map: BPTree::with_comparator(MultiKeyOrder(comparator)),
+            next_seq: 0,
+        }
+    }
+
+    /// Returns the number of values in the multimap, counting every value under
+    /// a duplicate key.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the multimap contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes all entries from the multimap.
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.next_seq = 0;
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, C: Comparator<K>> BPMultiMap<FANOUT, K, V, C> {
+    /// The bounds of every entry with a key equal to `key`.
+    fn key_bounds(key: &K) -> (Bound<MultiKey<K>>, Bound<MultiKey<K>>) {
+        let first = MultiKey {
+            key: key.clone(),
+            seq: 0,
+        };
+        let last = MultiKey {
+            key: key.clone(),
+            seq: u64::MAX,
+        };
+        (Bound::Included(first), Bound::Included(last))
+    }
+
+    /// Adds a value under `key`, after any values already under an equal key.
+    pub fn insert(&mut self, key: K, value: V) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.map.insert(MultiKey { key, seq }, value);
+    }
+
+    /// Returns `true` if the multimap contains at least one value for `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_all(key).next().is_some()
+    }
+
+    /// Gets an iterator over every value under `key`, in insertion order.
+    pub fn get_all(&self, key: &K) -> GetAll<'_, FANOUT, K, V> {
+        GetAll {
+            inner: self.map.range(Self::key_bounds(key)),
+        }
+    }
+
+    /// Removes the first value inserted under `key`, and returns it.
+    pub fn remove_one(&mut self, key: &K) -> Option<V> {
+        let (first, _) = self.map.range(Self::key_bounds(key)).next()?;
+        let first = first.clone();
+        self.map.remove(&first)
+    }
+
+    /// Removes every value under `key`, and returns them in insertion order.
+    pub fn remove_all(&mut self, key: &K) -> Vec<V> {
+        let keys: Vec<MultiKey<K>> = self
+            .map
+            .range(Self::key_bounds(key))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.iter()
+            .filter_map(|key| self.map.remove(key))
+            .collect()
+    }
+
+    /// Gets an iterator over the entries of the multimap, sorted by key, with
+    /// the values under equal keys in insertion order.
+    pub fn iter(&self) -> Iter<'_, FANOUT, K, V> {
+        Iter {
+            inner: self.map.iter(),
+        }
+    }
+}
+
+/// An iterator over the values under one key of a `BPMultiMap`.
+pub struct GetAll<'a, const FANOUT: usize, K: Clone, V> {
+    inner: bp_iter::Range<'a, FANOUT, MultiKey<K>, V>,
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V> Iterator for GetAll<'a, FANOUT, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V> DoubleEndedIterator for GetAll<'_, FANOUT, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, value)| value)
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V> FusedIterator for GetAll<'_, FANOUT, K, V> {}
+
+/// An iterator over the entries of a `BPMultiMap`, in key order.
+pub struct Iter<'a, const FANOUT: usize, K: Clone, V> {
+    inner: TreeIter<'a, FANOUT, MultiKey<K>, V>,
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V> Iterator for Iter<'a, FANOUT, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, value)| (&key.key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V> DoubleEndedIterator for Iter<'_, FANOUT, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, value)| (&key.key, value))
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V> ExactSizeIterator for Iter<'_, FANOUT, K, V> {}
+
+impl<const FANOUT: usize, K: Clone, V> FusedIterator for Iter<'_, FANOUT, K, V> {}
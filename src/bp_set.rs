@@ -0,0 +1,365 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::iter::{FusedIterator, Peekable};
+use std::ops::RangeBounds;
+
+use crate::bp_comparator::{Comparator, NaturalOrder};
+use crate::bp_iter::{self, Keys};
+use crate::bp_tree::BPTree;
+
+/// An ordered set based on a `BPTree`, with elements ordered by the comparator `C`.
+///
+/// The set algebra operations are lazy iterators that merge the leaf chains of
+/// the two sets in a single pass, instead of looking every element up in the
+/// other set.
+pub struct BPTreeSet<const FANOUT: usize, T: Clone, C = NaturalOrder> {
+    map: BPTree<FANOUT, T, (), C>,
+}
+
+impl<const FANOUT: usize, T: Clone + Debug, C: Comparator<T>> Debug for BPTreeSet<FANOUT, T, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<const FANOUT: usize, T: Clone, C: Default> Default for BPTreeSet<FANOUT, T, C> {
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<const FANOUT: usize, T: Clone + Ord> BPTreeSet<FANOUT, T> {
+    pub fn new() -> Self {
+        Self::with_comparator(NaturalOrder)
+    }
+}
+
+impl<const FANOUT: usize, T: Clone, C> BPTreeSet<FANOUT, T, C> {
+    /// Makes a new, empty set whose elements are ordered by `comparator`.
+    pub fn with_comparator(comparator: C) -> Self {
+        BPTreeSet {
+            map: BPTree::with_comparator(comparator),
+        }
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the set contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes all elements from the set.
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+}
+
+impl<const FANOUT: usize, T: Clone, C: Comparator<T>> BPTreeSet<FANOUT, T, C> {
+    /// Adds a value to the set, and returns whether it was newly inserted.
+    ///
+    /// If the set already had an equal value, it is not updated.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.map.insert(value, ()).is_none()
+    }
+
+    /// Returns `true` if the set contains a value equal to `value`.
+    pub fn contains<Q: ?Sized>(&self, value: &Q) -> bool
+    where
+        T: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.map.contains_key(value)
+    }
+
+    /// Returns a reference to the value in the set equal to `value`, if any.
+    pub fn get<Q: ?Sized>(&self, value: &Q) -> Option<&T>
+    where
+        T: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.map.get_key_value(value).map(|(key, _)| key)
+    }
+
+    /// Removes a value from the set, and returns whether it was present.
+    pub fn remove<Q: ?Sized>(&mut self, value: &Q) -> bool
+    where
+        T: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.map.remove(value).is_some()
+    }
+
+    /// Returns the first, minimum, element of the set.
+    pub fn first(&self) -> Option<&T> {
+        self.map.first_key_value().map(|(key, _)| key)
+    }
+
+    /// Returns the last, maximum, element of the set.
+    pub fn last(&self) -> Option<&T> {
+        self.map.last_key_value().map(|(key, _)| key)
+    }
+
+    /// Removes and returns the first element of the set.
+    pub fn pop_first(&mut self) -> Option<T> {
+        self.map.pop_first().map(|(key, _)| key)
+    }
+
+    /// Removes and returns the last element of the set.
+    pub fn pop_last(&mut self) -> Option<T> {
+        self.map.pop_last().map(|(key, _)| key)
+    }
+
+    /// Gets an iterator over the elements of the set, in order.
+    pub fn iter(&self) -> Iter<'_, FANOUT, T> {
+        Iter {
+            inner: self.map.keys(),
+        }
+    }
+
+    /// Gets an iterator over the elements of the set that fall in `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than its end, or if both ends
+    /// are equal and excluded.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, FANOUT, T>
+    where
+        Q: ?Sized,
+        T: std::borrow::Borrow<Q>,
+        R: RangeBounds<Q>,
+        C: Comparator<Q>,
+    {
+        Range {
+            inner: self.map.range(range),
+        }
+    }
+
+    /// Visits the elements that are in `self` or in `other`, in order and
+    /// without duplicates.
+    pub fn union<'a>(&'a self, other: &'a Self) -> Union<'a, FANOUT, T, C> {
+        Union(MergeIter::new(self, other))
+    }
+
+    /// Visits the elements that are both in `self` and in `other`, in order.
+    pub fn intersection<'a>(&'a self, other: &'a Self) -> Intersection<'a, FANOUT, T, C> {
+        Intersection(MergeIter::new(self, other))
+    }
+
+    /// Visits the elements that are in `self` but not in `other`, in order.
+    pub fn difference<'a>(&'a self, other: &'a Self) -> Difference<'a, FANOUT, T, C> {
+        Difference(MergeIter::new(self, other))
+    }
+
+    /// Visits the elements that are in `self` or in `other` but not in both, in
+    /// order.
+    pub fn symmetric_difference<'a>(
+        &'a self,
+        other: &'a Self,
+    ) -> SymmetricDifference<'a, FANOUT, T, C> {
+        SymmetricDifference(MergeIter::new(self, other))
+    }
+}
+
+/// An iterator over the elements of a `BPTreeSet`, in order.
+pub struct Iter<'a, const FANOUT: usize, T: Clone> {
+    inner: Keys<'a, FANOUT, T, ()>,
+}
+
+impl<'a, const FANOUT: usize, T: Clone> Iterator for Iter<'a, FANOUT, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<const FANOUT: usize, T: Clone> DoubleEndedIterator for Iter<'_, FANOUT, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl<const FANOUT: usize, T: Clone> ExactSizeIterator for Iter<'_, FANOUT, T> {}
+
+impl<const FANOUT: usize, T: Clone> FusedIterator for Iter<'_, FANOUT, T> {}
+
+/// An iterator over a sub-range of the elements of a `BPTreeSet`, in order.
+pub struct Range<'a, const FANOUT: usize, T: Clone> {
+    inner: bp_iter::Range<'a, FANOUT, T, ()>,
+}
+
+impl<'a, const FANOUT: usize, T: Clone> Iterator for Range<'a, FANOUT, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+}
+
+impl<const FANOUT: usize, T: Clone> DoubleEndedIterator for Range<'_, FANOUT, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<const FANOUT: usize, T: Clone> FusedIterator for Range<'_, FANOUT, T> {}
+
+/// Walks the elements of two sets side by side, pairing up equal elements.
+struct MergeIter<'a, const FANOUT: usize, T: Clone, C> {
+    a: Peekable<Iter<'a, FANOUT, T>>,
+    b: Peekable<Iter<'a, FANOUT, T>>,
+    cmp: &'a C,
+}
+
+impl<'a, const FANOUT: usize, T: Clone, C: Comparator<T>> MergeIter<'a, FANOUT, T, C> {
+    fn new(a: &'a BPTreeSet<FANOUT, T, C>, b: &'a BPTreeSet<FANOUT, T, C>) -> Self {
+        MergeIter {
+            a: a.iter().peekable(),
+            b: b.iter().peekable(),
+            cmp: a.map.comparator(),
+        }
+    }
+
+    /// The next element of either set, along with an equal element of the other
+    /// set if it has one.
+    fn next(&mut self) -> Option<(Option<&'a T>, Option<&'a T>)> {
+        let order = match (self.a.peek(), self.b.peek()) {
+            (None, None) => return None,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(a), Some(b)) => self.cmp.compare(a, b),
+        };
+        Some(match order {
+            Ordering::Less => (self.a.next(), None),
+            Ordering::Greater => (None, self.b.next()),
+            Ordering::Equal => (self.a.next(), self.b.next()),
+        })
+    }
+
+    fn lens(&self) -> (usize, usize) {
+        (self.a.len(), self.b.len())
+    }
+}
+
+/// A lazy iterator producing the elements of the union of two `BPTreeSet`s.
+///
+/// This is constructed from the [`union`](BPTreeSet::union) method.
+pub struct Union<'a, const FANOUT: usize, T: Clone, C>(MergeIter<'a, FANOUT, T, C>);
+
+impl<'a, const FANOUT: usize, T: Clone, C: Comparator<T>> Iterator for Union<'a, FANOUT, T, C> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (a, b) = self.0.next()?;
+        a.or(b)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a, b) = self.0.lens();
+        (a.max(b), Some(a + b))
+    }
+}
+
+impl<const FANOUT: usize, T: Clone, C: Comparator<T>> FusedIterator for Union<'_, FANOUT, T, C> {}
+
+/// A lazy iterator producing the elements of the intersection of two `BPTreeSet`s.
+///
+/// This is constructed from the [`intersection`](BPTreeSet::intersection) method.
+pub struct Intersection<'a, const FANOUT: usize, T: Clone, C>(MergeIter<'a, FANOUT, T, C>);
+
+impl<'a, const FANOUT: usize, T: Clone, C: Comparator<T>> Iterator
+    for Intersection<'a, FANOUT, T, C>
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // once either side runs out, nothing else can be in both
+        while self.0.a.peek().is_some() && self.0.b.peek().is_some() {
+            if let (Some(a), Some(_)) = self.0.next()? {
+                return Some(a);
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a, b) = self.0.lens();
+        (0, Some(a.min(b)))
+    }
+}
+
+impl<const FANOUT: usize, T: Clone, C: Comparator<T>> FusedIterator
+    for Intersection<'_, FANOUT, T, C>
+{
+}
+
+/// A lazy iterator producing the elements of the difference of two `BPTreeSet`s.
+///
+/// This is constructed from the [`difference`](BPTreeSet::difference) method.
+pub struct Difference<'a, const FANOUT: usize, T: Clone, C>(MergeIter<'a, FANOUT, T, C>);
+
+impl<'a, const FANOUT: usize, T: Clone, C: Comparator<T>> Iterator
+    for Difference<'a, FANOUT, T, C>
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // once `self` runs out, the rest of `other` does not matter
+        while self.0.a.peek().is_some() {
+            if let (Some(a), None) = self.0.next()? {
+                return Some(a);
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a, b) = self.0.lens();
+        (a.saturating_sub(b), Some(a))
+    }
+}
+
+impl<const FANOUT: usize, T: Clone, C: Comparator<T>> FusedIterator
+    for Difference<'_, FANOUT, T, C>
+{
+}
+
+/// A lazy iterator producing the elements of the symmetric difference of two
+/// `BPTreeSet`s.
+///
+/// This is constructed from the
+/// [`symmetric_difference`](BPTreeSet::symmetric_difference) method.
+pub struct SymmetricDifference<'a, const FANOUT: usize, T: Clone, C>(MergeIter<'a, FANOUT, T, C>);
+
+impl<'a, const FANOUT: usize, T: Clone, C: Comparator<T>> Iterator
+    for SymmetricDifference<'a, FANOUT, T, C>
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (a, b) = self.0.next()?;
+            if let Some(value) = a.xor(b) {
+                return Some(value);
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a, b) = self.0.lens();
+        (0, Some(a + b))
+    }
+}
+
+impl<const FANOUT: usize, T: Clone, C: Comparator<T>> FusedIterator
+    for SymmetricDifference<'_, FANOUT, T, C>
+{
+}
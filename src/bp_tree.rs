@@ -1,14 +1,184 @@
+use std::cmp::Ordering;
 use std::collections::VecDeque;
 use std::fmt::Debug;
-use std::ops::Deref;
+use std::hash::{Hash, Hasher};
+use std::ops::{Bound, Deref, Index, RangeBounds};
 
+use crate::bp_aggregate::Aggregate;
+use crate::bp_comparator::{Comparator, NaturalOrder};
+use crate::bp_cursor::{Cursor, CursorMut};
+use crate::bp_entry::{Entry, OccupiedEntry};
+use crate::bp_iter::{ExtractIf, IntoIter, Iter, IterMut, Keys, Range, Values, ValuesMut};
 use crate::node::{BPNode, BPNodePtr};
 
-pub struct BPTree<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> {
-    pub(crate) root: BPNodePtr<FANOUT, K, V>,
+/// A B+ tree map, with keys ordered by the comparator `C`.
+///
+/// A tree can keep a summary of its entries, the aggregate `A`, cached in every
+/// index node. Such a tree gives out no plain `&mut V`, since the cached
+/// summaries could not follow the changes made through it: `get_mut`,
+/// `iter_mut`, `values_mut`, [`CursorMut::value_mut`] and the entry methods that
+/// return `&mut V` are only there when `A` is `()`. Values of an aggregate tree
+/// are changed with [`update`](BPTree::update), or through the entry API with
+/// [`Entry::or_insert_entry`] and [`OccupiedEntry::modify`], instead.
+pub struct BPTree<const FANOUT: usize, K: Clone, V, C = NaturalOrder, A = ()> {
+    pub(crate) root: BPNodePtr<FANOUT, K, V, A>,
+    pub(crate) length: usize,
+    pub(crate) comparator: C,
 }
 
-impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> Debug for BPTree<FANOUT, K, V> {
+/// The error returned by [`BPTree::try_insert`] when the key is already present.
+pub struct OccupiedError<'a, K, V> {
+    /// The key that was not inserted.
+    pub key: K,
+    /// The value that was not inserted.
+    pub value: V,
+    /// The value already stored under the key.
+    pub existing: &'a V,
+}
+
+impl<K: Debug, V: Debug> Debug for OccupiedError<'_, K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OccupiedError")
+            .field("key", &self.key)
+            .field("value", &self.value)
+            .field("existing", self.existing)
+            .finish()
+    }
+}
+
+impl<K: Debug, V: Debug> std::fmt::Display for OccupiedError<'_, K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "failed to insert {:?}, key {:?} already exists with value {:?}",
+            self.value, self.key, self.existing,
+        )
+    }
+}
+
+impl<K: Debug, V: Debug> std::error::Error for OccupiedError<'_, K, V> {}
+
+/// The error returned by [`BPTree::from_sorted_iter`] when the keys of its input
+/// are not strictly increasing.
+#[derive(Debug)]
+pub struct UnsortedError<K> {
+    /// The first key that is not greater than the key before it.
+    pub key: K,
+    /// The position of that key in the input.
+    pub position: usize,
+}
+
+impl<K: Debug> std::fmt::Display for UnsortedError<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "key {:?} at position {} is not greater than the key before it",
+            self.key, self.position,
+        )
+    }
+}
+
+impl<K: Debug> std::error::Error for UnsortedError<K> {}
+
+/// The error returned by [`BPTree::validate`] when the tree is not well formed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvariantViolation {
+    /// The indices of the children taken from the root down to the offending node,
+    /// empty for the root itself.
+    pub path: Vec<usize>,
+    /// The invariant the node breaks.
+    pub kind: ViolationKind,
+}
+
+/// An invariant of a `BPTree`, as broken by the node an [`InvariantViolation`]
+/// points at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The node holds `len` entries, or children for an index node, outside of
+    /// the `min..=max` allowed in its place.
+    Occupancy { len: usize, min: usize, max: usize },
+    /// The keys, children, counts and summaries of an index node are out of
+    /// step: it must hold one key fewer than children, and one count and one
+    /// summary per child.
+    Shape {
+        keys: usize,
+        children: usize,
+        counts: usize,
+        summaries: usize,
+    },
+    /// The key at `index` is not greater than the key before it.
+    Unsorted { index: usize },
+    /// The key at `index` is outside of the range given by the separators above
+    /// the node.
+    Separator { index: usize },
+    /// A leaf is at `depth`, while the leaves before it are at `expected`.
+    Depth { depth: usize, expected: usize },
+    /// The count cached for the child at `index` differs from its entries.
+    Count {
+        index: usize,
+        cached: usize,
+        actual: usize,
+    },
+    /// The length of the tree differs from the entries in it.
+    Length { cached: usize, actual: usize },
+    /// The `next` link of the node does not lead to the node right of it on the
+    /// same level.
+    NextLink,
+    /// The `prev` link of the node does not lead to the node left of it on the
+    /// same level.
+    PrevLink,
+}
+
+impl std::fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "node root")?;
+        for index in &self.path {
+            write!(f, "/{}", index)?;
+        }
+        match self.kind {
+            ViolationKind::Occupancy { len, min, max } => {
+                write!(f, " holds {}, outside of {}..={}", len, min, max)
+            }
+            ViolationKind::Shape {
+                keys,
+                children,
+                counts,
+                summaries,
+            } => write!(
+                f,
+                " has {} keys, {} children, {} counts and {} summaries",
+                keys, children, counts, summaries,
+            ),
+            ViolationKind::Unsorted { index } => {
+                write!(f, " has key {} not greater than the key before it", index)
+            }
+            ViolationKind::Separator { index } => {
+                write!(f, " has key {} outside of its separators", index)
+            }
+            ViolationKind::Depth { depth, expected } => {
+                write!(f, " has leaves at depth {}, other leaves are at {}", depth, expected)
+            }
+            ViolationKind::Count {
+                index,
+                cached,
+                actual,
+            } => write!(
+                f,
+                " caches {} entries for child {}, which has {}",
+                cached, index, actual,
+            ),
+            ViolationKind::Length { cached, actual } => {
+                write!(f, " is in a tree of length {} with {} entries", cached, actual)
+            }
+            ViolationKind::NextLink => write!(f, " is not linked to its next sibling"),
+            ViolationKind::PrevLink => write!(f, " is not linked to its previous sibling"),
+        }
+    }
+}
+
+impl std::error::Error for InvariantViolation {}
+
+impl<const FANOUT: usize, K: Clone + Debug, V: Debug, C, A: Aggregate<K, V>> Debug for BPTree<FANOUT, K, V, C, A> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let node = &self.root;
         let mut queue = VecDeque::new();
@@ -31,27 +201,212 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> Debug for BPT
     }
 }
 
-impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPTree<FANOUT, K, V> {
+impl<const FANOUT: usize, K: Clone, V, C: Default, A: Aggregate<K, V>> Default for BPTree<FANOUT, K, V, C, A> {
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V: Clone, C: Clone, A: Aggregate<K, V>> Clone for BPTree<FANOUT, K, V, C, A> {
+    /// Copies every node of the tree, instead of sharing them with the original.
+    fn clone(&self) -> Self {
+        BPTree {
+            root: BPNode::deep_clone(&self.root),
+            length: self.length,
+            comparator: self.comparator.clone(),
+        }
+    }
+}
+
+impl<const FANOUT: usize, K: Clone + PartialEq, V: PartialEq, C: Comparator<K>, A: Aggregate<K, V>> PartialEq
+    for BPTree<FANOUT, K, V, C, A>
+{
+    /// Two trees are equal if they have equal entries in the same order, however
+    /// their nodes are laid out.
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<const FANOUT: usize, K: Clone + Eq, V: Eq, C: Comparator<K>, A: Aggregate<K, V>> Eq for BPTree<FANOUT, K, V, C, A> {}
+
+impl<const FANOUT: usize, K: Clone + Hash, V: Hash, C: Comparator<K>, A: Aggregate<K, V>> Hash
+    for BPTree<FANOUT, K, V, C, A>
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len());
+        for entry in self.iter() {
+            entry.hash(state);
+        }
+    }
+}
+
+impl<const FANOUT: usize, K: Clone + PartialOrd, V: PartialOrd, C: Comparator<K>, A: Aggregate<K, V>> PartialOrd
+    for BPTree<FANOUT, K, V, C, A>
+{
+    /// Compares the entries of the two trees lexicographically, in tree order.
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<const FANOUT: usize, K: Clone + Ord, V: Ord, C: Comparator<K>, A: Aggregate<K, V>> Ord for BPTree<FANOUT, K, V, C, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, C: Comparator<K> + Default, A: Aggregate<K, V>> FromIterator<(K, V)>
+    for BPTree<FANOUT, K, V, C, A>
+{
+    /// Sorts the entries and bulk loads them; like repeated inserts, a duplicate
+    /// key keeps its first occurrence with the value of its last one.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let comparator = C::default();
+        let mut entries: Vec<(K, V)> = iter.into_iter().collect();
+        entries.sort_by(|a, b| comparator.compare(&a.0, &b.0));
+        entries.dedup_by(|later, earlier| {
+            let duplicate = comparator.compare(&earlier.0, &later.0).is_eq();
+            if duplicate {
+                std::mem::swap(&mut earlier.1, &mut later.1);
+            }
+            duplicate
+        });
+        BPTree {
+            length: entries.len(),
+            root: BPNode::from_sorted_entries(entries, 1.0),
+            comparator,
+        }
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, C: Comparator<K>, A: Aggregate<K, V>> Extend<(K, V)> for BPTree<FANOUT, K, V, C, A> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, C, A: Aggregate<K, V>> IntoIterator for BPTree<FANOUT, K, V, C, A> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<FANOUT, K, V, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self)
+    }
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, C: Comparator<K>, A: Aggregate<K, V>> IntoIterator
+    for &'a BPTree<FANOUT, K, V, C, A>
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, FANOUT, K, V, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, const FANOUT: usize, K: Clone, V, C: Comparator<K>> IntoIterator
+    for &'a mut BPTree<FANOUT, K, V, C>
+{
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, FANOUT, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, C, A: Aggregate<K, V>, Q: ?Sized> Index<&Q> for BPTree<FANOUT, K, V, C, A>
+where
+    K: std::borrow::Borrow<Q>,
+    C: Comparator<K> + Comparator<Q>,
+{
+    type Output = V;
+
+    /// Returns a reference to the value corresponding to the supplied key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not present in the tree.
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<const FANOUT: usize, K: Clone + Ord, V, A: Aggregate<K, V>> BPTree<FANOUT, K, V, NaturalOrder, A> {
     pub fn new() -> Self {
+        Self::with_comparator(NaturalOrder)
+    }
+
+    pub fn new_from(root: BPNodePtr<FANOUT, K, V, A>) -> Self {
+        let mut tree = BPTree {
+            root,
+            length: 0,
+            comparator: NaturalOrder,
+        };
+        tree.length = tree.iter().count();
+        tree
+    }
+
+    /// Builds a tree from entries sorted by strictly increasing key, filling
+    /// every node to `fill_factor` of its capacity.
+    ///
+    /// Unlike repeated inserts this never splits or rebalances a node: the leaves
+    /// are packed and linked first, then the index levels are built over them.
+    /// A `fill_factor` below 1 leaves room for later inserts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fill_factor` is not in `(0, 1]`.
+    pub fn from_sorted_iter<I>(iter: I, fill_factor: f64) -> Result<Self, UnsortedError<K>>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        Self::from_sorted_iter_with_comparator(iter, fill_factor, NaturalOrder)
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, C, A: Aggregate<K, V>> BPTree<FANOUT, K, V, C, A> {
+    /// Makes a new, empty tree whose keys are ordered by `comparator` instead of
+    /// by `Ord`.
+    pub fn with_comparator(comparator: C) -> Self {
         BPTree {
             root: BPNode::new_leaf_ptr(),
+            length: 0,
+            comparator,
         }
     }
 
-    pub fn new_from(root: BPNodePtr<FANOUT, K, V>) -> Self {
-        BPTree { root }
+    /// Returns a reference to the comparator that orders the keys of the tree.
+    pub fn comparator(&self) -> &C {
+        &self.comparator
     }
 
-    fn root_replace(&mut self, new_root: BPNodePtr<FANOUT, K, V>) -> BPNodePtr<FANOUT, K, V> {
-        std::mem::replace(&mut self.root, new_root)
+    /// Returns the number of entries in the tree.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    /// Returns `true` if the tree contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
     }
 
-    pub fn search(&self, key: &K) -> Option<V> {
-        BPNode::search_recur(&self.root, key)
+    /// Removes all entries from the tree.
+    pub fn clear(&mut self) {
+        self.root_replace(BPNode::new_leaf_ptr());
+        self.length = 0;
     }
 
-    pub fn insert(&mut self, key: K, value: V) {
-        BPNode::insert_recur(&mut self.root, key, value);
+    fn root_replace(&mut self, new_root: BPNodePtr<FANOUT, K, V, A>) -> BPNodePtr<FANOUT, K, V, A> {
+        std::mem::replace(&mut self.root, new_root)
+    }
+
+    /// Grows the tree by one level if the root became full.
+    pub(crate) fn split_root(&mut self) {
         if self.root.borrow().deref().is_full() {
             let old_root = self.root_replace(BPNode::new_index_ptr());
             let (split_key, right) = BPNode::split_node(&old_root);
@@ -63,12 +418,621 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPTree<FANOUT
         }
     }
 
-    pub fn remove(&mut self, key: &K) {
-        BPNode::remove_recur(&mut self.root, key);
-        if self.root.borrow().is_empty() {
+    /// Shrinks the tree while the root is an index node left with a single child.
+    pub(crate) fn collapse_root(&mut self) {
+        loop {
+            let collapse = {
+                let root = self.root.borrow();
+                root.is_index() && root.is_empty()
+            };
+            if !collapse {
+                return;
+            }
             let child = self.root.borrow_mut().as_index_mut().remove_child(0);
             self.root_replace(child);
         }
     }
+
+    /// Repairs every underflowing node of the tree, and refreshes every cached
+    /// count and summary, after entries were taken out of the leaves in place.
+    pub(crate) fn repair_all(&mut self) {
+        while BPNode::repair_recur(&self.root) {}
+        self.collapse_root();
+    }
+
+    /// Like [`repair_all`](BPTree::repair_all), when entries were only taken out
+    /// in front of the entry at `index` in the leaf reached by taking the children
+    /// at `path`, one per level.
+    ///
+    /// That front part is cut off and repaired node by node, and joined back to
+    /// the rest, of which only the nodes along the cut need repairs.
+    pub(crate) fn repair_prefix(&mut self, path: &[usize], index: usize) {
+        let total = self.length;
+        let mut rest = BPTree {
+            root: BPNode::split_off_at_path(&self.root, path, index),
+            length: 0,
+            comparator: (),
+        };
+        self.repair_all();
+        rest.repair_spine(false);
+        self.length = self.root.borrow().subtree_len();
+        rest.length = total - self.length;
+        if rest.length == 0 {
+            return;
+        }
+        let rest_root = rest.root_replace(BPNode::new_leaf_ptr());
+        if self.length == 0 {
+            self.root_replace(rest_root);
+        } else {
+            let separator = {
+                let leaf = BPNode::leftmost_leaf(&rest_root);
+                let first = leaf.borrow().as_leaf().get_key(0).unwrap().clone();
+                first
+            };
+            let front = self.root_replace(BPNode::new_leaf_ptr());
+            self.root_replace(BPNode::join(front, separator, rest_root));
+            self.split_root();
+            self.repair_spine(true);
+            self.repair_spine(false);
+        }
+        self.length = total;
+    }
+
+    /// Repairs the nodes on the rightmost (or, if `last` is false, the leftmost)
+    /// path of the tree, after a cut or a join left them underflowing.
+    pub(crate) fn repair_spine(&mut self, last: bool) {
+        loop {
+            self.collapse_root();
+            if !BPNode::repair_spine(&self.root, last) {
+                return;
+            }
+        }
+    }
+}
+
+impl<const FANOUT: usize, K: Clone, V, C: Comparator<K>, A: Aggregate<K, V>> BPTree<FANOUT, K, V, C, A> {
+    /// Like [`from_sorted_iter`](BPTree::from_sorted_iter), for entries sorted by
+    /// `comparator`.
+    pub fn from_sorted_iter_with_comparator<I>(
+        iter: I,
+        fill_factor: f64,
+        comparator: C,
+    ) -> Result<Self, UnsortedError<K>>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        assert!(
+            fill_factor > 0.0 && fill_factor <= 1.0,
+            "fill factor must be in (0, 1]"
+        );
+        let mut entries: Vec<(K, V)> = Vec::new();
+        for (position, (key, value)) in iter.into_iter().enumerate() {
+            if let Some((prev, _)) = entries.last() {
+                if comparator.compare(prev, &key).is_ge() {
+                    return Err(UnsortedError { key, position });
+                }
+            }
+            entries.push((key, value));
+        }
+        Ok(BPTree {
+            length: entries.len(),
+            root: BPNode::from_sorted_entries(entries, fill_factor),
+            comparator,
+        })
+    }
+
+    /// Returns a clone of the value corresponding to the key.
+    ///
+    /// Prefer [`get`](BPTree::get), which borrows the value instead.
+    pub fn search<Q: ?Sized>(&self, key: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+        V: Clone,
+    {
+        self.get(key).cloned()
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the tree's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    /// Returns the key-value pair corresponding to the supplied key.
+    pub fn get_key_value<Q: ?Sized>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        let leaf = BPNode::search_leaf(&self.root, key, &self.comparator);
+        // SAFETY: the returned references borrow `self`, so no `&mut` to the leaf
+        // can exist while they are alive.
+        let leaf = unsafe { BPNode::deref_unguarded(&leaf) }.as_leaf();
+        let index = leaf.search_key(key, &self.comparator).ok()?;
+        leaf.get_key_value(index)
+    }
+
+    /// Returns `true` if the tree contains a value for the specified key.
+    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Inserts a key-value pair into the tree.
+    ///
+    /// If the tree already had this key, its value is replaced and the old value
+    /// is returned; the key itself is not updated.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let old_value = BPNode::insert_recur(&self.root, key, value, &self.comparator);
+        self.split_root();
+        if old_value.is_none() {
+            self.length += 1;
+        }
+        old_value
+    }
+
+    /// Inserts a key-value pair into the tree, unless the key is already present.
+    ///
+    /// On a duplicate key nothing is updated, and the error carries the rejected
+    /// key and value along with the value already in the tree.
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<(), OccupiedError<'_, K, V>> {
+        let leaf = BPNode::search_leaf(&self.root, &key, &self.comparator);
+        // SAFETY: the reference is only returned on a duplicate key, when the tree
+        // is left alone; otherwise it is dead before `insert` mutates the tree.
+        let leaf = unsafe { BPNode::deref_unguarded(&leaf) }.as_leaf();
+        if let Ok(index) = leaf.search_key(&key, &self.comparator) {
+            return Err(OccupiedError {
+                key,
+                value,
+                existing: leaf.get_value(index).unwrap(),
+            });
+        }
+        self.insert(key, value);
+        Ok(())
+    }
+
+    /// Calls `f` on the value corresponding to the key, if there is one, and
+    /// returns its result.
+    ///
+    /// Unlike [`get_mut`](BPTree::get_mut) this works on trees that keep an
+    /// aggregate, as the summaries cached on the path down to the value are
+    /// refreshed, bottom-up, once `f` returns.
+    pub fn update<Q: ?Sized, R, F>(&mut self, key: &Q, f: F) -> Option<R>
+    where
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+        F: FnOnce(&mut V) -> R,
+    {
+        let (path, leaf) = BPNode::search_path(&self.root, key, &self.comparator);
+        let result = {
+            let mut node = leaf.borrow_mut();
+            let lnode = node.as_leaf_mut();
+            let index = lnode.search_key(key, &self.comparator).ok()?;
+            f(lnode.get_value_mut(index).unwrap())
+        };
+        for (node, child_index, _) in path.iter().rev() {
+            node.borrow_mut().as_index_mut().refresh(*child_index);
+        }
+        Some(result)
+    }
+
+    /// Gets the given key's corresponding entry in the tree for in-place
+    /// manipulation.
+    pub fn entry(&mut self, key: K) -> Entry<'_, FANOUT, K, V, C, A> {
+        Entry::new(self, key)
+    }
+
+    /// Returns the first key-value pair in the tree, the one with the minimum key.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        let leaf = BPNode::leftmost_leaf(&self.root);
+        // SAFETY: the returned references borrow `self`, so no `&mut` to the leaf
+        // can exist while they are alive.
+        unsafe { BPNode::deref_unguarded(&leaf) }
+            .as_leaf()
+            .get_key_value(0)
+    }
+
+    /// Returns the last key-value pair in the tree, the one with the maximum key.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let leaf = BPNode::rightmost_leaf(&self.root);
+        // SAFETY: the returned references borrow `self`, so no `&mut` to the leaf
+        // can exist while they are alive.
+        let leaf = unsafe { BPNode::deref_unguarded(&leaf) }.as_leaf();
+        leaf.get_key_value(leaf.len().checked_sub(1)?)
+    }
+
+    /// Returns the entry at position `index` in key order, counting from zero.
+    ///
+    /// Every index node keeps the number of entries under each of its children,
+    /// so this descends straight to the leaf holding the entry.
+    pub fn nth(&self, index: usize) -> Option<(&K, &V)> {
+        let (leaf, index) = BPNode::select(&self.root, index)?;
+        // SAFETY: the returned references borrow `self`, so no `&mut` to the leaf
+        // can exist while they are alive.
+        unsafe { BPNode::deref_unguarded(&leaf) }
+            .as_leaf()
+            .get_key_value(index)
+    }
+
+    /// Returns the number of entries with keys less than `key`, which is the
+    /// position `key` has, or would have, in key order.
+    pub fn rank<Q: ?Sized>(&self, key: &Q) -> usize
+    where
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        BPNode::rank(&self.root, key, &self.comparator, false)
+    }
+
+    /// Returns the number of entries whose keys fall in `range`, without visiting
+    /// them. A range whose start is past its end holds no entries.
+    pub fn count_range<Q, R>(&self, range: R) -> usize
+    where
+        Q: ?Sized,
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(start) => BPNode::rank(&self.root, start, &self.comparator, false),
+            Bound::Excluded(start) => BPNode::rank(&self.root, start, &self.comparator, true),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => BPNode::rank(&self.root, end, &self.comparator, true),
+            Bound::Excluded(end) => BPNode::rank(&self.root, end, &self.comparator, false),
+            Bound::Unbounded => self.length,
+        };
+        end.saturating_sub(start)
+    }
+
+    /// Returns the summary of the entries whose keys fall in `range`.
+    ///
+    /// The summaries cached in the index nodes stand in for every subtree inside
+    /// the range, so only the paths down to its two ends are visited. A range
+    /// whose start is past its end holds no entries.
+    pub fn aggregate<Q, R>(&self, range: R) -> A
+    where
+        Q: ?Sized,
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+    {
+        BPNode::aggregate(&self.root, range.start_bound(), range.end_bound(), &self.comparator)
+    }
+
+    /// Returns the first entry in the tree for in-place manipulation.
+    pub fn first_entry(&mut self) -> Option<OccupiedEntry<'_, FANOUT, K, V, C, A>> {
+        OccupiedEntry::edge(self, false)
+    }
+
+    /// Returns the last entry in the tree for in-place manipulation.
+    pub fn last_entry(&mut self) -> Option<OccupiedEntry<'_, FANOUT, K, V, C, A>> {
+        OccupiedEntry::edge(self, true)
+    }
+
+    /// Removes and returns the first entry in the tree, the one with the minimum key.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        self.first_entry().map(|entry| entry.remove_entry())
+    }
+
+    /// Removes and returns the last entry in the tree, the one with the maximum key.
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        self.last_entry().map(|entry| entry.remove_entry())
+    }
+
+    /// Removes a key from the tree, returning its value if the key was present.
+    pub fn remove<Q: ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        let removed = BPNode::remove_recur(&self.root, key, &self.comparator);
+        self.collapse_root();
+        if removed.is_some() {
+            self.length -= 1;
+        }
+        removed
+    }
+
+    /// Splits the tree in two at the given key: the entries with keys greater
+    /// than or equal to `key` move to the returned tree.
+    ///
+    /// The leaf chain and every level above it are cut along the path to `key`,
+    /// and only the nodes along the cut are merged or rebalanced afterwards. The
+    /// lengths of both trees come from the cached subtree counts, so the whole
+    /// split takes logarithmic time.
+    pub fn split_off<Q: ?Sized>(&mut self, key: &Q) -> Self
+    where
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q> + Clone,
+    {
+        self.split_off_inner(key, false)
+    }
+
+    /// Splits the tree in two at the given key, like `split_off`, but keeps an
+    /// entry with a key equal to `key` in `self` if `inclusive` is true.
+    fn split_off_inner<Q: ?Sized>(&mut self, key: &Q, inclusive: bool) -> Self
+    where
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q> + Clone,
+    {
+        let mut right = BPTree {
+            root: BPNode::split_off_recur(&self.root, key, &self.comparator, inclusive),
+            length: 0,
+            comparator: self.comparator.clone(),
+        };
+        self.repair_spine(true);
+        right.repair_spine(false);
+
+        // the cached counts of the two roots give the lengths without a walk
+        let total = self.length;
+        self.length = self.root.borrow().subtree_len();
+        right.length = total - self.length;
+        right
+    }
+
+    /// Removes every entry with a key in `range`, and returns how many were
+    /// removed.
+    ///
+    /// The tree is cut at both ends of the range and the two outer parts are
+    /// joined back together, so the leaves and subtrees in between are dropped
+    /// whole and only the nodes along the two cuts are repaired.
+    pub fn remove_range<Q: ?Sized, R>(&mut self, range: R) -> usize
+    where
+        K: std::borrow::Borrow<Q>,
+        R: RangeBounds<Q>,
+        C: Comparator<Q> + Clone,
+    {
+        let mut removed = match range.start_bound() {
+            Bound::Included(start) => self.split_off_inner(start, false),
+            Bound::Excluded(start) => self.split_off_inner(start, true),
+            Bound::Unbounded => {
+                let empty = BPTree::with_comparator(self.comparator.clone());
+                std::mem::replace(self, empty)
+            }
+        };
+        let mut rest = match range.end_bound() {
+            Bound::Included(end) => removed.split_off_inner(end, true),
+            Bound::Excluded(end) => removed.split_off_inner(end, false),
+            Bound::Unbounded => BPTree::with_comparator(self.comparator.clone()),
+        };
+        self.append(&mut rest);
+        removed.len()
+    }
+
+    /// Moves all entries from `other` into `self`, leaving `other` empty.
+    ///
+    /// If all keys of one tree are less than those of the other, the shorter tree
+    /// is spliced into the spine of the taller one, linking up the leaf chains.
+    /// Otherwise the entries of both are merged and the tree is rebuilt, with the
+    /// values of `other` winning for keys present in both. The comparator of
+    /// `self` orders the result.
+    pub fn append(&mut self, other: &mut Self) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            std::mem::swap(&mut self.root, &mut other.root);
+            std::mem::swap(&mut self.length, &mut other.length);
+            return;
+        }
+
+        let length = self.length + other.length;
+        other.length = 0;
+        // the side that goes first, and the separator between the two
+        let split = {
+            let self_first = self.first_key_value().unwrap().0;
+            let self_last = self.last_key_value().unwrap().0;
+            let other_first = other.first_key_value().unwrap().0;
+            let other_last = other.last_key_value().unwrap().0;
+            if self.comparator.compare(self_last, other_first).is_lt() {
+                Some((true, other_first.clone()))
+            } else if self.comparator.compare(other_last, self_first).is_lt() {
+                Some((false, self_first.clone()))
+            } else {
+                None
+            }
+        };
+        let mine = self.root_replace(BPNode::new_leaf_ptr());
+        let theirs = other.root_replace(BPNode::new_leaf_ptr());
+        match split {
+            Some((self_first, separator)) => {
+                let (left, right) = if self_first { (mine, theirs) } else { (theirs, mine) };
+                self.root_replace(BPNode::join(left, separator, right));
+                self.split_root();
+                self.repair_spine(true);
+                self.repair_spine(false);
+                self.length = length;
+            }
+            None => {
+                let mine = IntoIter::new(BPTree {
+                    root: mine,
+                    length: self.length,
+                    comparator: (),
+                });
+                let theirs = IntoIter::new(BPTree {
+                    root: theirs,
+                    length: length - self.length,
+                    comparator: (),
+                });
+                let entries = self.merge_entries(mine, theirs);
+                self.length = entries.len();
+                self.root_replace(BPNode::from_sorted_entries(entries, 1.0));
+            }
+        }
+    }
+
+    /// Merges two sequences of entries sorted by key, keeping the key of `a` and
+    /// the value of `b` for keys present in both.
+    fn merge_entries(
+        &self,
+        a: impl Iterator<Item = (K, V)>,
+        b: impl Iterator<Item = (K, V)>,
+    ) -> Vec<(K, V)> {
+        let (mut a, mut b) = (a.peekable(), b.peekable());
+        let mut entries = Vec::new();
+        loop {
+            let order = match (a.peek(), b.peek()) {
+                (None, None) => return entries,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((x, _)), Some((y, _))) => self.comparator.compare(x, y),
+            };
+            match order {
+                Ordering::Less => entries.extend(a.next()),
+                Ordering::Greater => entries.extend(b.next()),
+                Ordering::Equal => {
+                    let (key, _) = a.next().unwrap();
+                    let (_, value) = b.next().unwrap();
+                    entries.push((key, value));
+                }
+            }
+        }
+    }
+
+    /// Gets an iterator over the entries of the tree, sorted by key.
+    pub fn iter(&self) -> Iter<'_, FANOUT, K, V, A> {
+        Iter {
+            inner: Range::new(self, ..),
+            length: self.length,
+        }
+    }
+
+    /// Gets an iterator over the keys of the tree, in sorted order.
+    pub fn keys(&self) -> Keys<'_, FANOUT, K, V, A> {
+        Keys { inner: self.iter() }
+    }
+
+    /// Gets an iterator over the values of the tree, in order by key.
+    pub fn values(&self) -> Values<'_, FANOUT, K, V, A> {
+        Values { inner: self.iter() }
+    }
+
+    /// Retains only the entries for which `f` returns `true`, visiting them in key
+    /// order.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.extract_if(|key, value| !f(key, value)).for_each(drop);
+    }
+
+    /// Creates an iterator that visits the entries in key order, and removes and
+    /// yields those for which `pred` returns `true`.
+    ///
+    /// Entries are taken out of the leaves as the iterator walks the leaf chain,
+    /// and the tree is rebalanced once the iterator is dropped. Entries it does
+    /// not reach stay in the tree. If the iterator is leaked instead of dropped,
+    /// the tree is left empty.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, FANOUT, K, V, C, F, A>
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        ExtractIf::new(self, pred)
+    }
+
+    /// Gets an iterator over the entries whose keys fall in `range`, sorted by key.
+    ///
+    /// The iterator descends once to the first leaf in range and then scans the
+    /// leaf chain.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than its end, or if both ends
+    /// are equal and excluded.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, FANOUT, K, V, A>
+    where
+        Q: ?Sized,
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+    {
+        Range::new(self, range)
+    }
+
+    /// Checks that the tree is well formed: every node but the root is between
+    /// half full and full, keys are sorted and fall between the separators above
+    /// them, all leaves are at the same depth, the nodes of each level are linked
+    /// to their siblings both ways, and the cached counts and length are right.
+    ///
+    /// The summaries of the aggregate are not compared, as `A` need not be
+    /// comparable, but there must be one for every child.
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        let mut levels = Vec::new();
+        let actual = BPNode::validate_recur(
+            &self.root,
+            &mut Vec::new(),
+            None,
+            None,
+            &self.comparator,
+            &mut None,
+            &mut levels,
+        )?;
+        for level in &levels {
+            BPNode::validate_links(level)?;
+        }
+        if actual != self.length {
+            return Err(InvariantViolation {
+                path: Vec::new(),
+                kind: ViolationKind::Length {
+                    cached: self.length,
+                    actual,
+                },
+            });
+        }
+        Ok(())
+    }
+
+    /// Gets a cursor over the entries of the tree, on the first entry.
+    pub fn cursor(&self) -> Cursor<'_, FANOUT, K, V, C, A> {
+        Cursor::new(self)
+    }
+
+    /// Gets a cursor over the entries of the tree that can insert and remove
+    /// entries where it stands, on the first entry.
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, FANOUT, K, V, C, A> {
+        CursorMut::new(self)
+    }
+}
+
+/// Mutable access to the values in place, which is only given out by trees that
+/// keep no aggregate, as the cached summaries could not follow the changes.
+impl<const FANOUT: usize, K: Clone, V, C: Comparator<K>> BPTree<FANOUT, K, V, C> {
+    /// Returns a mutable reference to the value corresponding to the key, so it
+    /// can be edited in place without being cloned or reinserted.
+    pub fn get_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: std::borrow::Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        let leaf = BPNode::search_leaf(&self.root, key, &self.comparator);
+        // SAFETY: the returned reference borrows `self` exclusively, and is the only
+        // one derived from the leaf.
+        let leaf = unsafe { BPNode::deref_unguarded_mut(&leaf) }.as_leaf_mut();
+        let index = leaf.search_key(key, &self.comparator).ok()?;
+        leaf.get_value_mut(index)
+    }
+
+    /// Gets a mutable iterator over the entries of the tree, sorted by key.
+    pub fn iter_mut(&mut self) -> IterMut<'_, FANOUT, K, V> {
+        IterMut::new(self)
+    }
+
+    /// Gets a mutable iterator over the values of the tree, in order by key.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, FANOUT, K, V> {
+        ValuesMut {
+            inner: self.iter_mut(),
+        }
+    }
 }
- 
@@ -1,3 +1,10 @@
+pub mod bp_aggregate;
+pub mod bp_comparator;
+pub mod bp_cursor;
+pub mod bp_entry;
+pub mod bp_iter;
+pub mod bp_multimap;
+pub mod bp_set;
 pub mod bp_tree;
 mod node;
  
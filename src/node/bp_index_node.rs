@@ -1,19 +1,25 @@
 use super::{BPNode, BPNodePtr, BPNodeWeak};
+use crate::bp_aggregate::Aggregate;
+use crate::bp_comparator::Comparator;
 use std::cell::RefCell;
 use std::fmt::Debug;
 use std::ops::DerefMut;
 use std::rc::Rc;
 
-pub struct BPIndexNode<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> {
+pub struct BPIndexNode<const FANOUT: usize, K: Clone, V, A> {
     keys: Vec<K>,
-    children: Vec<BPNodePtr<FANOUT, K, V>>,
-    parent: Option<BPNodeWeak<FANOUT, K, V>>,
-    pub prev: Option<BPNodeWeak<FANOUT, K, V>>,
-    pub next: Option<BPNodePtr<FANOUT, K, V>>,
+    children: Vec<BPNodePtr<FANOUT, K, V, A>>,
+    /// The number of entries under each child, in step with `children`.
+    counts: Vec<usize>,
+    /// The summary of the entries under each child, in step with `children`.
+    summaries: Vec<A>,
+    parent: Option<BPNodeWeak<FANOUT, K, V, A>>,
+    pub prev: Option<BPNodeWeak<FANOUT, K, V, A>>,
+    pub next: Option<BPNodePtr<FANOUT, K, V, A>>,
 }
 
-impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> Debug
-    for BPIndexNode<FANOUT, K, V>
+impl<const FANOUT: usize, K: Clone + Debug, V: Debug, A> Debug
+    for BPIndexNode<FANOUT, K, V, A>
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -25,11 +31,13 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> Debug
     }
 }
 
-impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPIndexNode<FANOUT, K, V> {
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> BPIndexNode<FANOUT, K, V, A> {
     pub fn new() -> Self {
         BPIndexNode {
             keys: Vec::new(),
             children: Vec::new(),
+            counts: Vec::new(),
+            summaries: Vec::new(),
             parent: None,
             prev: None,
             next: None,
@@ -38,14 +46,23 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPIndexNode<F
 
     pub fn new_with(
         keys: Vec<K>,
-        children: Vec<BPNodePtr<FANOUT, K, V>>,
-        parent: Option<BPNodeWeak<FANOUT, K, V>>,
-        prev: Option<BPNodeWeak<FANOUT, K, V>>,
-        next: Option<BPNodePtr<FANOUT, K, V>>,
+        children: Vec<BPNodePtr<FANOUT, K, V, A>>,
+        parent: Option<BPNodeWeak<FANOUT, K, V, A>>,
+        prev: Option<BPNodeWeak<FANOUT, K, V, A>>,
+        next: Option<BPNodePtr<FANOUT, K, V, A>>,
     ) -> Self {
+        let (counts, summaries) = children
+            .iter()
+            .map(|child| {
+                let child = child.borrow();
+                (child.subtree_len(), child.summary())
+            })
+            .unzip();
         BPIndexNode {
             keys,
             children,
+            counts,
+            summaries,
             parent,
             prev,
             next,
@@ -57,15 +74,15 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPIndexNode<F
     }
 
     pub fn is_maxinum(&self) -> bool {
-        self.children.len() == FANOUT - 1
+        self.keys.len() == FANOUT - 1
     }
 
     pub fn is_minimum(&self) -> bool {
-        self.children.len() == (FANOUT + 1) / 2
+        self.children.len() == FANOUT.div_ceil(2)
     }
 
     pub fn is_underflow(&self) -> bool {
-        self.children.len() < (FANOUT + 1) / 2
+        self.children.len() < FANOUT.div_ceil(2)
     }
 
     pub fn is_empty(&self) -> bool {
@@ -76,24 +93,62 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPIndexNode<F
         self.keys.get(index)
     }
 
-    pub fn get_child(&self, index: usize) -> Option<&BPNodePtr<FANOUT, K, V>> {
+    pub fn get_child(&self, index: usize) -> Option<&BPNodePtr<FANOUT, K, V, A>> {
         self.children.get(index)
     }
 
-    pub fn get_child_clone(&self, index: usize) -> Option<BPNodePtr<FANOUT, K, V>> {
+    pub fn get_child_clone(&self, index: usize) -> Option<BPNodePtr<FANOUT, K, V, A>> {
         let child = self.children.get(index)?;
         Some(child.clone())
     }
 
-    pub fn get_child_mut(&mut self, index: usize) -> Option<&mut BPNodePtr<FANOUT, K, V>> {
+    pub fn get_child_mut(&mut self, index: usize) -> Option<&mut BPNodePtr<FANOUT, K, V, A>> {
         self.children.get_mut(index)
     }
 
-    pub fn get_children(&self) -> &Vec<BPNodePtr<FANOUT, K, V>> {
+    pub fn get_keys(&self) -> &Vec<K> {
+        &self.keys
+    }
+
+    pub fn get_children(&self) -> &Vec<BPNodePtr<FANOUT, K, V, A>> {
         &self.children
     }
 
-    pub fn get_parent(&self) -> Option<&BPNodeWeak<FANOUT, K, V>> {
+    pub fn get_counts(&self) -> &Vec<usize> {
+        &self.counts
+    }
+
+    /// Lets the tests put a wrong count in the cache.
+    #[cfg(test)]
+    pub(crate) fn counts_mut(&mut self) -> &mut Vec<usize> {
+        &mut self.counts
+    }
+
+    /// The number of entries in the subtree under this node.
+    pub fn subtree_len(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    pub fn get_summaries(&self) -> &Vec<A> {
+        &self.summaries
+    }
+
+    /// The summary of the entries in the subtree under this node.
+    pub fn summary(&self) -> A {
+        self.summaries
+            .iter()
+            .fold(A::identity(), |summary, child| summary.combine(child))
+    }
+
+    /// Refreshes the count and the summary of the child at `index`, after entries
+    /// were added to, removed from or changed in its subtree.
+    pub(crate) fn refresh(&mut self, index: usize) {
+        let child = self.children[index].borrow();
+        self.counts[index] = child.subtree_len();
+        self.summaries[index] = child.summary();
+    }
+
+    pub fn get_parent(&self) -> Option<&BPNodeWeak<FANOUT, K, V, A>> {
         self.parent.as_ref()
     }
 
@@ -101,7 +156,9 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPIndexNode<F
         self.keys.push(key);
     }
 
-    pub fn push_child(&mut self, child: BPNodePtr<FANOUT, K, V>) {
+    pub fn push_child(&mut self, child: BPNodePtr<FANOUT, K, V, A>) {
+        self.counts.push(child.borrow().subtree_len());
+        self.summaries.push(child.borrow().summary());
         self.children.push(child);
     }
 
@@ -117,40 +174,84 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPIndexNode<F
         self.keys[index] = key;
     }
 
-    pub(crate) fn insert_child_at(&mut self, index: usize, child: BPNodePtr<FANOUT, K, V>) {
+    pub(crate) fn insert_child_at(&mut self, index: usize, child: BPNodePtr<FANOUT, K, V, A>) {
+        self.counts.insert(index, child.borrow().subtree_len());
+        self.summaries.insert(index, child.borrow().summary());
         self.children.insert(index, child);
     }
 
-    pub fn remove_child(&mut self, index: usize) -> BPNodePtr<FANOUT, K, V> {
+    pub fn remove_child(&mut self, index: usize) -> BPNodePtr<FANOUT, K, V, A> {
+        self.counts.remove(index);
+        self.summaries.remove(index);
         self.children.remove(index)
     }
 
-    pub fn search_key(&self, key: &K) -> Result<usize, usize> {
-        self.keys.binary_search(key)
+    pub fn search_key<Q: ?Sized, C: Comparator<Q>>(&self, key: &Q, cmp: &C) -> Result<usize, usize>
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        self.keys
+            .binary_search_by(|probe| cmp.compare(std::borrow::Borrow::<Q>::borrow(probe), key))
     }
 
-    pub fn get_index_of(&self, key: &K) -> (bool, usize) {
-        match self.keys.binary_search(key) {
+    pub fn get_index_of<Q: ?Sized, C: Comparator<Q>>(&self, key: &Q, cmp: &C) -> (bool, usize)
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        match self.search_key(key, cmp) {
             Ok(index) => (true, index + 1),
             Err(index) => (false, index),
         }
     }
 
-    pub fn split_node(node: &BPNodePtr<FANOUT, K, V>,inode: &mut BPIndexNode<FANOUT, K, V>) -> (K, BPNodePtr<FANOUT, K, V>) {
-        let split_key = *inode.get_key(FANOUT / 2).unwrap();
+    pub fn split_node(node: &BPNodePtr<FANOUT, K, V, A>,inode: &mut BPIndexNode<FANOUT, K, V, A>) -> (K, BPNodePtr<FANOUT, K, V, A>) {
         let new_index = BPIndexNode::new_with(
             inode.keys.split_off(FANOUT / 2 + 1),
             inode.children.split_off(FANOUT / 2 + 1),
             inode.parent.clone(),
-            Some(Rc::<RefCell<BPNode<FANOUT, K, V>>>::downgrade(node)),
+            Some(Rc::<RefCell<BPNode<FANOUT, K, V, A>>>::downgrade(node)),
             inode.next.clone()
         );
-        inode.keys.pop();
+        inode.counts.truncate(FANOUT / 2 + 1);
+        inode.summaries.truncate(FANOUT / 2 + 1);
+        // the middle key moves up rather than being copied
+        let split_key = inode.keys.pop().unwrap();
         let new_index_ptr = BPNode::new_index_ptr_from(new_index);
+        if let Some(next) = inode.next.as_ref() {
+            next.borrow_mut().as_index_mut().prev = Some(Rc::downgrade(&new_index_ptr));
+        }
         inode.next = Some(new_index_ptr.clone());
         (split_key, new_index_ptr)
     }
 
+    /// Moves the children after `index`, and the separators from `index` on, into
+    /// a new node whose first child is `right`, the part of child `index` cut off
+    /// from it. The new node takes over the link to the next node of the level.
+    pub fn split_off_at(
+        inode: &mut BPIndexNode<FANOUT, K, V, A>,
+        index: usize,
+        right: BPNodePtr<FANOUT, K, V, A>,
+    ) -> BPNodePtr<FANOUT, K, V, A> {
+        let mut children = inode.children.split_off(index + 1);
+        children.insert(0, right);
+        inode.counts.truncate(index + 1);
+        inode.summaries.truncate(index + 1);
+        inode.refresh(index);
+        let next = inode.next.take();
+        let new_index = BPIndexNode::new_with(
+            inode.keys.split_off(index),
+            children,
+            None,
+            None,
+            next.clone(),
+        );
+        let new_index_ptr = BPNode::new_index_ptr_from(new_index);
+        if let Some(next) = next {
+            next.borrow_mut().as_index_mut().prev = Some(Rc::downgrade(&new_index_ptr));
+        }
+        new_index_ptr
+    }
+
     pub fn merge_children(&mut self, to_remove: usize, merge_into_left: bool) {
         // pop the key between the two children
         let key_index = if merge_into_left {
@@ -161,7 +262,7 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPIndexNode<F
         let key = self.keys.remove(key_index);
 
         // pop the child
-        let child = self.children.remove(to_remove);
+        let child = self.remove_child(to_remove);
 
         let target_index = if merge_into_left {
             to_remove - 1
@@ -177,31 +278,43 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPIndexNode<F
                 leaf.merge(child.as_leaf_mut(), merge_into_left);
             }
             BPNode::Index(index) => {
+                // the key between the two children moves down between their children
                 let mut child = child.borrow_mut();
-                let child0 = child.as_index_mut().remove_child(0);
+                let child = child.as_index_mut();
                 if merge_into_left {
                     index.keys.push(key);
-                    index.children.push(child0);
-                    index.next = child.as_index_mut().next.take();
+                    index.keys.append(&mut child.keys);
+                    index.children.append(&mut child.children);
+                    index.counts.append(&mut child.counts);
+                    index.summaries.append(&mut child.summaries);
+                    index.next = child.next.take();
+                    if let Some(next) = index.next.as_ref() {
+                        next.borrow_mut().as_index_mut().prev = child.prev.take();
+                    }
                 } else {
-                    index.keys.insert(0, key);
-                    index.children.insert(0, child0);
-                    index.prev = child.as_index_mut().prev.take();
+                    child.keys.push(key);
+                    index.keys.splice(0..0, child.keys.drain(..));
+                    index.children.splice(0..0, child.children.drain(..));
+                    index.counts.splice(0..0, child.counts.drain(..));
+                    index.summaries.splice(0..0, child.summaries.drain(..));
+                    index.prev = child.prev.take();
+                    if let Some(prev) = index.prev.as_ref() {
+                        prev.upgrade().unwrap().borrow_mut().as_index_mut().next = child.next.take();
+                    }
                 }
             }
         }
+        self.refresh(target_index);
     }
 
     pub fn rebalance_children(&mut self, target_index: usize, rebalance_from_left: bool) {
-        // pop the key between the two children
+        // the key between the two children
         let key_index = if rebalance_from_left {
             target_index - 1
         } else {
             target_index
         };
-        let key = self.keys.remove(key_index);
 
-        //
         let from_index = if rebalance_from_left {
             target_index - 1
         } else {
@@ -214,25 +327,117 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPIndexNode<F
 
         match target.borrow_mut().deref_mut() {
             BPNode::Leaf(leaf) => {
-                leaf.steal(from.as_leaf_mut(), rebalance_from_left);
+                leaf.steal(from.as_leaf_mut(), !rebalance_from_left);
+                // the separator is the first key of the right one of the two children
+                let right = if rebalance_from_left {
+                    leaf
+                } else {
+                    from.as_leaf_mut()
+                };
+                self.set_key(key_index, right.get_key(0).unwrap().clone());
             }
             BPNode::Index(index) => {
-                assert!(index.keys.len() == 0);
-                assert!(index.children.len() == 1);
+                // rotate through the parent: the separator moves down into the target
+                // and the sibling's boundary key moves up to replace it
                 let from = from.as_index_mut();
-
+                let key = self.keys.remove(key_index);
                 if rebalance_from_left {
                     index.keys.insert(0, key);
-                    index.children.insert(0, from.remove_child(0));
-                    let from_key = from.remove_key(0);
-                    self.insert_key_at(key_index, from_key);
+                    index.children.insert(0, from.children.pop().unwrap());
+                    index.counts.insert(0, from.counts.pop().unwrap());
+                    index.summaries.insert(0, from.summaries.pop().unwrap());
+                    self.insert_key_at(key_index, from.keys.pop().unwrap());
                 } else {
                     index.keys.push(key);
+                    index.counts.push(from.counts[0]);
+                    index.summaries.push(from.summaries[0].clone());
                     index.children.push(from.remove_child(0));
                     self.insert_key_at(key_index, from.remove_key(0));
                 }
             }
         };
+        // the guards on both children must be released before they are refreshed
+        drop(from);
+        self.refresh(target_index);
+        self.refresh(from_index);
+    }
+
+    /// Splits the child at `index` if an insertion made it full, or, like a B* tree,
+    /// hands its last entry to the next sibling if that one still has room.
+    ///
+    /// The count of the child is refreshed first, as every insertion passes here.
+    pub(crate) fn fix_overflow_child(&mut self, index: usize) {
+        self.refresh(index);
+        let child = self.get_child_clone(index).unwrap();
+        if !child.borrow().is_full() {
+            return;
+        }
+        let next_has_room = self
+            .get_child(index + 1)
+            .is_some_and(|next| !next.borrow().is_maxinum());
+        if next_has_room {
+            self.rebalance_children(index + 1, true);
+        } else {
+            let (split_key, right) = BPNode::split_node(&child);
+            self.insert_key_at(index, split_key);
+            self.insert_child_at(index + 1, right);
+            self.refresh(index);
+        }
+    }
+
+    /// Merges or rebalances the child at `child_index` if a removal made it
+    /// underflow. `exist` tells whether the removed key was the separator right
+    /// before that child, which then gets replaced by its successor.
+    ///
+    /// The count of the child is refreshed first, as every removal passes here.
+    pub(crate) fn fix_underflow_child(&mut self, child_index: usize, exist: bool) {
+        self.refresh(child_index);
+        let underflow = self.get_child(child_index).unwrap().borrow().is_underflow();
+        if underflow {
+            // If the child node is underflow, merge or rebalance it with its sibling node
+            // It is guaranteed that the sibling node is not empty
+            let sibling_index = self.get_sibiling_index(child_index);
+            let sibiling_is_left = sibling_index < child_index;
+            let sibling = self.get_child(sibling_index).unwrap();
+            if sibling.borrow().is_minimum() {
+                // if the sibling node is minimum, merge it with the child node
+                self.merge_children(child_index, sibiling_is_left);
+            } else {
+                // if the sibling node is not minimum, rebalance it with the child node
+                self.rebalance_children(child_index, sibiling_is_left);
+            }
+        } else if exist {
+            // Find the successor and replace the key
+            let child = self.get_child(child_index).unwrap();
+            let successor = BPNode::minimum(child);
+            self.set_key(child_index - 1, successor);
+        }
+    }
+
+    /// Merges or rebalances the child at `child_index` until it no longer
+    /// underflows, however far under its minimum it is, and returns whether
+    /// anything changed along with the index the child ends up at.
+    pub(crate) fn repair_child(&mut self, child_index: usize) -> (bool, usize) {
+        let mut index = child_index;
+        let mut changed = false;
+        while self.children.len() > 1 && self.children[index].borrow().is_underflow() {
+            let sibling_index = self.get_sibiling_index(index);
+            let sibiling_is_left = sibling_index < index;
+            let fits = {
+                let child = self.children[index].borrow();
+                child.can_merge_with(&self.children[sibling_index].borrow())
+            };
+            if fits {
+                self.merge_children(index, sibiling_is_left);
+                if sibiling_is_left {
+                    index -= 1;
+                }
+            } else {
+                self.rebalance_children(index, sibiling_is_left);
+            }
+            changed = true;
+        }
+        (changed, index)
     }
 
     pub fn get_sibiling_index(&self, index: usize) -> usize {
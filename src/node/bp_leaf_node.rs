@@ -1,18 +1,21 @@
+use std::borrow::Borrow;
 use std::fmt::Debug;
 use std::{cell::RefCell, rc::Rc};
 
 use super::{BPNode, BPNodePtr, BPNodeWeak};
+use crate::bp_aggregate::Aggregate;
+use crate::bp_comparator::Comparator;
 
-pub struct BPLeafNode<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> {
+pub struct BPLeafNode<const FANOUT: usize, K: Clone, V, A> {
     keys: Vec<K>,
     values: Vec<V>,
-    parent: Option<BPNodeWeak<FANOUT, K, V>>,
-    pub prev: Option<BPNodeWeak<FANOUT, K, V>>,
-    pub next: Option<BPNodePtr<FANOUT, K, V>>,
+    parent: Option<BPNodeWeak<FANOUT, K, V, A>>,
+    pub prev: Option<BPNodeWeak<FANOUT, K, V, A>>,
+    pub next: Option<BPNodePtr<FANOUT, K, V, A>>,
 }
 
-impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> Debug
-    for BPLeafNode<FANOUT, K, V>
+impl<const FANOUT: usize, K: Clone + Debug, V: Debug, A> Debug
+    for BPLeafNode<FANOUT, K, V, A>
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("BPLeafNode")
@@ -22,7 +25,7 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> Debug
     }
 }
 
-impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPLeafNode<FANOUT, K, V> {
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> BPLeafNode<FANOUT, K, V, A> {
     pub fn new() -> Self {
         BPLeafNode {
             keys: Vec::new(),
@@ -36,9 +39,9 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPLeafNode<FA
     pub fn new_with(
         keys: Vec<K>,
         values: Vec<V>,
-        parent: Option<BPNodeWeak<FANOUT, K, V>>,
-        prev: Option<BPNodeWeak<FANOUT, K, V>>,
-        next: Option<BPNodePtr<FANOUT, K, V>>,
+        parent: Option<BPNodeWeak<FANOUT, K, V, A>>,
+        prev: Option<BPNodeWeak<FANOUT, K, V, A>>,
+        next: Option<BPNodePtr<FANOUT, K, V, A>>,
     ) -> Self {
         BPLeafNode {
             keys,
@@ -69,6 +72,10 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPLeafNode<FA
         self.keys.is_empty()
     }
 
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
     pub fn is_root(&self) -> bool {
         self.parent.is_none()
     }
@@ -81,8 +88,49 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPLeafNode<FA
         self.values.get(index)
     }
 
-    pub fn delete(&mut self, key: &K) -> bool {
-        let index = self.keys.binary_search(key).ok();
+    pub fn get_keys(&self) -> &Vec<K> {
+        &self.keys
+    }
+
+    pub fn get_values(&self) -> &Vec<V> {
+        &self.values
+    }
+
+    pub fn get_value_mut(&mut self, index: usize) -> Option<&mut V> {
+        self.values.get_mut(index)
+    }
+
+    /// The summary of the entries in `range` of positions in the leaf.
+    pub fn summary_of(&self, range: std::ops::Range<usize>) -> A {
+        self.keys[range.clone()]
+            .iter()
+            .zip(&self.values[range])
+            .fold(A::identity(), |summary, (key, value)| {
+                summary.combine(&A::from_entry(key, value))
+            })
+    }
+
+    /// The summary of all the entries in the leaf.
+    pub fn summary(&self) -> A {
+        self.summary_of(0..self.keys.len())
+    }
+
+    /// Borrows the keys and the values of the leaf at the same time.
+    pub fn entries_mut(&mut self) -> (&[K], &mut [V]) {
+        (&self.keys, &mut self.values)
+    }
+
+    /// Moves all the keys and values out of the leaf, leaving it empty.
+    pub fn take_entries(&mut self) -> (Vec<K>, Vec<V>) {
+        (std::mem::take(&mut self.keys), std::mem::take(&mut self.values))
+    }
+
+    pub fn get_key_value(&self, index: usize) -> Option<(&K, &V)> {
+        Some((self.keys.get(index)?, self.values.get(index)?))
+    }
+
+    pub fn delete<C: Comparator<K>>(&mut self, key: &K, cmp: &C) -> bool {
+        let index = self.search_key(key, cmp).ok();
         if let Some(index) = index {
             self.keys.remove(index);
             self.values.remove(index);
@@ -102,8 +150,8 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPLeafNode<FA
         }
     }
 
-    pub fn insert(&mut self, key: K, value: V) -> bool {
-        let index = match self.keys.binary_search(&key) {
+    pub fn insert<C: Comparator<K>>(&mut self, key: K, value: V, cmp: &C) -> bool {
+        let index = match self.search_key(&key, cmp) {
             Ok(_) => return false,
             Err(index) => index,
         };
@@ -113,24 +161,51 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPLeafNode<FA
     }
 
     pub fn split_leaf_node(
-        node: &BPNodePtr<FANOUT, K, V>,
-        leaf: &mut BPLeafNode<FANOUT, K, V>,
-    ) -> (K, BPNodePtr<FANOUT, K, V>) {
-        let split_key = *leaf.get_key(FANOUT / 2).unwrap();
+        node: &BPNodePtr<FANOUT, K, V, A>,
+        leaf: &mut BPLeafNode<FANOUT, K, V, A>,
+    ) -> (K, BPNodePtr<FANOUT, K, V, A>) {
+        let split_key = leaf.get_key(FANOUT / 2).unwrap().clone();
         let new_leaf = BPLeafNode::new_with(
             leaf.keys.split_off(FANOUT / 2),
             leaf.values.split_off(FANOUT / 2),
             leaf.parent.clone(),
-            Some(Rc::<RefCell<BPNode<FANOUT, K, V>>>::downgrade(node)),
+            Some(Rc::<RefCell<BPNode<FANOUT, K, V, A>>>::downgrade(node)),
             leaf.next.clone(),
         );
         let new_leaf_ptr = BPNode::new_leaf_ptr_from(new_leaf);
+        if let Some(next) = leaf.next.as_ref() {
+            next.borrow_mut().as_leaf_mut().prev = Some(Rc::downgrade(&new_leaf_ptr));
+        }
         leaf.next = Some(new_leaf_ptr.clone());
         (split_key, new_leaf_ptr)
     }
 
-    pub fn search_key(&self, key: &K) -> Result<usize, usize> {
-        self.keys.binary_search(key)
+    /// Moves the entries from `index` on into a new leaf, and cuts the leaf chain
+    /// between the two: the new leaf takes over the link to the next leaf.
+    pub fn split_off_at(
+        leaf: &mut BPLeafNode<FANOUT, K, V, A>,
+        index: usize,
+    ) -> BPNodePtr<FANOUT, K, V, A> {
+        let next = leaf.next.take();
+        let new_leaf = BPLeafNode::new_with(
+            leaf.keys.split_off(index),
+            leaf.values.split_off(index),
+            None,
+            None,
+            next.clone(),
+        );
+        let new_leaf_ptr = BPNode::new_leaf_ptr_from(new_leaf);
+        if let Some(next) = next {
+            next.borrow_mut().as_leaf_mut().prev = Some(Rc::downgrade(&new_leaf_ptr));
+        }
+        new_leaf_ptr
+    }
+
+    pub fn search_key<Q: ?Sized, C: Comparator<Q>>(&self, key: &Q, cmp: &C) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+    {
+        self.keys.binary_search_by(|probe| cmp.compare(probe.borrow(), key))
     }
 
     pub fn push_key_value(&mut self, key: K, value: V) {
@@ -143,7 +218,7 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPLeafNode<FA
         self.values.insert(index, value);
     }
 
-    pub fn merge(&mut self, other: &mut BPLeafNode<FANOUT, K, V>, other_is_next: bool) {
+    pub fn merge(&mut self, other: &mut BPLeafNode<FANOUT, K, V, A>, other_is_next: bool) {
         if other_is_next {
             self.keys.append(&mut other.keys);
             self.values.append(&mut other.values);
@@ -153,8 +228,8 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPLeafNode<FA
             }
         } else {
             // merge to front
-            self.keys.insert(0, other.keys.pop().unwrap());
-            self.values.insert(0, other.values.pop().unwrap());
+            self.keys.splice(0..0, other.keys.drain(..));
+            self.values.splice(0..0, other.values.drain(..));
             self.prev = other.prev.take();
             if let Some(prev) = self.prev.as_ref() {
                 prev.upgrade().unwrap().borrow_mut().as_leaf_mut().next = other.next.take();
@@ -163,7 +238,7 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPLeafNode<FA
         other.parent.take();
     }
 
-    pub fn steal(&mut self, other: &mut BPLeafNode<FANOUT, K, V>, other_is_next: bool) {
+    pub fn steal(&mut self, other: &mut BPLeafNode<FANOUT, K, V, A>, other_is_next: bool) {
         if other_is_next {
             self.keys.push(other.keys.remove(0));
             self.values.push(other.values.remove(0));
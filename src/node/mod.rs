@@ -2,27 +2,37 @@ mod bp_index_node;
 mod bp_leaf_node;
 use std::{
     fmt::Debug,
-    ops::{Deref, DerefMut},
+    ops::{Bound, Deref, DerefMut},
 };
 
 pub use bp_index_node::BPIndexNode;
 pub use bp_leaf_node::BPLeafNode;
 
+use crate::bp_aggregate::Aggregate;
+use crate::bp_comparator::Comparator;
+use crate::bp_tree::{InvariantViolation, ViolationKind};
+
 use std::{
     cell::RefCell,
     rc::{Rc, Weak},
 };
 
-pub type BPNodePtr<const FANOUT: usize, K, V> = Rc<RefCell<BPNode<FANOUT, K, V>>>;
-pub type BPNodeWeak<const FANOUT: usize, K, V> = Weak<RefCell<BPNode<FANOUT, K, V>>>;
+pub type BPNodePtr<const FANOUT: usize, K, V, A> = Rc<RefCell<BPNode<FANOUT, K, V, A>>>;
+pub type BPNodeWeak<const FANOUT: usize, K, V, A> = Weak<RefCell<BPNode<FANOUT, K, V, A>>>;
+/// The index nodes visited on the way down to a leaf, each with the index of the
+/// child taken and whether the key matched the separator right before it.
+pub type BPPath<const FANOUT: usize, K, V, A> = Vec<(BPNodePtr<FANOUT, K, V, A>, usize, bool)>;
+/// The nodes of one level of the tree from left to right, each with the indices
+/// of the children taken down to it from the root.
+pub type BPLevel<const FANOUT: usize, K, V, A> = Vec<(BPNodePtr<FANOUT, K, V, A>, Vec<usize>)>;
 
 #[derive(Debug)]
-pub enum BPNode<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> {
-    Index(BPIndexNode<FANOUT, K, V>),
-    Leaf(BPLeafNode<FANOUT, K, V>),
+pub enum BPNode<const FANOUT: usize, K: Clone, V, A> {
+    Index(BPIndexNode<FANOUT, K, V, A>),
+    Leaf(BPLeafNode<FANOUT, K, V, A>),
 }
 
-impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPNode<FANOUT, K, V> {
+impl<const FANOUT: usize, K: Clone, V, A: Aggregate<K, V>> BPNode<FANOUT, K, V, A> {
     pub fn new_leaf() -> Self {
         BPNode::Leaf(BPLeafNode::new())
     }
@@ -31,19 +41,19 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPNode<FANOUT
         BPNode::Index(BPIndexNode::new())
     }
 
-    pub fn new_leaf_ptr() -> BPNodePtr<FANOUT, K, V> {
+    pub fn new_leaf_ptr() -> BPNodePtr<FANOUT, K, V, A> {
         Rc::new(RefCell::new(BPNode::new_leaf()))
     }
 
-    pub fn new_index_ptr() -> BPNodePtr<FANOUT, K, V> {
+    pub fn new_index_ptr() -> BPNodePtr<FANOUT, K, V, A> {
         Rc::new(RefCell::new(BPNode::new_index()))
     }
 
-    pub fn new_leaf_ptr_from(lnode: BPLeafNode<FANOUT, K, V>) -> BPNodePtr<FANOUT, K, V> {
+    pub fn new_leaf_ptr_from(lnode: BPLeafNode<FANOUT, K, V, A>) -> BPNodePtr<FANOUT, K, V, A> {
         Rc::new(RefCell::new(BPNode::Leaf(lnode)))
     }
 
-    pub fn new_index_ptr_from(inode: BPIndexNode<FANOUT, K, V>) -> BPNodePtr<FANOUT, K, V> {
+    pub fn new_index_ptr_from(inode: BPIndexNode<FANOUT, K, V, A>) -> BPNodePtr<FANOUT, K, V, A> {
         Rc::new(RefCell::new(BPNode::Index(inode)))
     }
 
@@ -61,28 +71,28 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPNode<FANOUT
         }
     }
 
-    pub fn as_leaf(&self) -> &BPLeafNode<FANOUT, K, V> {
+    pub fn as_leaf(&self) -> &BPLeafNode<FANOUT, K, V, A> {
         match self {
             BPNode::Leaf(leaf) => leaf,
             BPNode::Index(_) => panic!("not a leaf node"),
         }
     }
 
-    pub fn as_index(&self) -> &BPIndexNode<FANOUT, K, V> {
+    pub fn as_index(&self) -> &BPIndexNode<FANOUT, K, V, A> {
         match self {
             BPNode::Leaf(_) => panic!("not an index node"),
             BPNode::Index(index) => index,
         }
     }
 
-    pub fn as_leaf_mut(&mut self) -> &mut BPLeafNode<FANOUT, K, V> {
+    pub fn as_leaf_mut(&mut self) -> &mut BPLeafNode<FANOUT, K, V, A> {
         match self {
             BPNode::Leaf(leaf) => leaf,
             BPNode::Index(_) => panic!("not a leaf node"),
         }
     }
 
-    pub fn as_index_mut(&mut self) -> &mut BPIndexNode<FANOUT, K, V> {
+    pub fn as_index_mut(&mut self) -> &mut BPIndexNode<FANOUT, K, V, A> {
         match self {
             BPNode::Leaf(_) => panic!("not an index node"),
             BPNode::Index(index) => index,
@@ -124,164 +134,921 @@ impl<const FANOUT: usize, K: Copy + Ord + Debug, V: Clone + Debug> BPNode<FANOUT
         }
     }
 
-    pub fn search_key(&self, key: &K) -> Result<usize, usize> {
+    /// The number of entries in the subtree under this node.
+    pub fn subtree_len(&self) -> usize {
+        match self {
+            BPNode::Leaf(leaf) => leaf.len(),
+            BPNode::Index(index) => index.subtree_len(),
+        }
+    }
+
+    /// The summary of the entries in the subtree under this node.
+    pub fn summary(&self) -> A {
         match self {
-            BPNode::Leaf(leaf) => leaf.search_key(key),
-            BPNode::Index(index) => index.search_key(key),
+            BPNode::Leaf(leaf) => leaf.summary(),
+            BPNode::Index(index) => index.summary(),
         }
     }
 
-    pub(crate) fn insert_recur(root: &BPNodePtr<FANOUT, K, V>, key: K, value: V) {
+    /// Whether the entries (or children) of the two nodes fit in a single node.
+    pub fn can_merge_with(&self, other: &Self) -> bool {
+        match (self, other) {
+            (BPNode::Leaf(a), BPNode::Leaf(b)) => a.len() + b.len() < FANOUT,
+            (BPNode::Index(a), BPNode::Index(b)) => {
+                a.get_children().len() + b.get_children().len() <= FANOUT
+            }
+            _ => unreachable!("siblings are on the same level"),
+        }
+    }
+
+    pub fn search_key<Q: ?Sized, C: Comparator<Q>>(&self, key: &Q, cmp: &C) -> Result<usize, usize>
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        match self {
+            BPNode::Leaf(leaf) => leaf.search_key(key, cmp),
+            BPNode::Index(index) => index.search_key(key, cmp),
+        }
+    }
+
+    pub(crate) fn insert_recur<C: Comparator<K>>(
+        root: &BPNodePtr<FANOUT, K, V, A>,
+        key: K,
+        value: V,
+        cmp: &C,
+    ) -> Option<V> {
         let mut root = root.borrow_mut();
 
         if root.is_empty() {
             root.as_leaf_mut().push_key_value(key, value);
-            return;
+            return None;
         }
 
-        let index = root.search_key(&key);
-        if let Ok(_) = index {
-            // If the key is already in the tree, do nothing.
-            return;
-        } else if let Err(index) = index {
-            // If the key is not in the tree
-            match root.deref_mut() {
-                BPNode::Leaf(lroot) => {
+        match root.deref_mut() {
+            BPNode::Leaf(lroot) => match lroot.search_key(&key, cmp) {
+                // If the key is already in the tree, replace its value.
+                Ok(index) => Some(std::mem::replace(
+                    lroot.get_value_mut(index).unwrap(),
+                    value,
+                )),
+                Err(index) => {
                     lroot.insert_key_value(index, key, value);
+                    None
                 }
-                BPNode::Index(iroot) => {
-                    let child_num = iroot.get_children().len();
-                    let mut change_key=iroot.get_key(0).unwrap().clone();
-                    let mut old_key = iroot.get_key(0).unwrap().clone();
-                    let child = iroot.get_child_mut(index).unwrap();
-                    Self::insert_recur(child, key, value);
-                    if child.borrow_mut().is_full() {
-                        let mut is_changed = false;
-                        if index < child_num-1 {
-                            {
-                                let mut cb = child.borrow_mut();
-                                match cb.deref(){
-                                    BPNode::Leaf(lroot) => {
-                                        if let Some(nextnode) = lroot.next.clone() {
-                                            if ! nextnode.borrow_mut().is_maxinum(){
-                                                let removal = cb.as_leaf_mut().remove(FANOUT-1);
-                                                is_changed = true;
-                                                if let Some(tmp_removel) = removal{
-                                                    change_key = tmp_removel.0;
-                                                    let val = tmp_removel.1;
-                                                    old_key = *nextnode.borrow_mut().as_leaf_mut().get_key(0).unwrap();
-                                                    nextnode.borrow_mut().as_leaf_mut().insert(change_key.clone(), val);
-                                                }
-                                            }
-                                        }
-                                    }
-                                    BPNode::Index(iroot) => { 
-                                        if let Some(nextnode) = iroot.next.clone() {
-                                            if ! nextnode.borrow_mut().is_maxinum(){
-                                                is_changed = true;
-                                                change_key = child.borrow_mut().as_index_mut().remove_key(FANOUT-1);
-                                                old_key = *nextnode.borrow_mut().as_index_mut().get_key(0).unwrap();
-                                                nextnode.borrow_mut().as_index_mut().insert_key_at(0, change_key.clone());
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        if is_changed{
-                            let (is_find, ind) = iroot.get_index_of(&old_key);
-                            if is_find{
-                                iroot.set_key(ind-1, change_key);
-                            }
-                        }
-                        else {
-                            let (split_key, right) = Self::split_node(&child);
-                            iroot.insert_key_at(index, split_key);
-                            iroot.insert_child_at(index + 1, right);
-                        }
-                    }
-                }
+            },
+            BPNode::Index(iroot) => {
+                let (_, index) = iroot.get_index_of(&key, cmp);
+                let child = iroot.get_child_clone(index).unwrap();
+                let old_value = Self::insert_recur(&child, key, value, cmp);
+                iroot.fix_overflow_child(index);
+                old_value
             }
         }
     }
 
-    pub(crate) fn remove_recur(root: &BPNodePtr<FANOUT, K, V>, key: &K) {
+    pub(crate) fn remove_recur<Q: ?Sized, C: Comparator<Q>>(
+        root: &BPNodePtr<FANOUT, K, V, A>,
+        key: &Q,
+        cmp: &C,
+    ) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+    {
         let mut root = root.borrow_mut();
 
         if root.is_empty() {
-            return;
+            return None;
         }
 
         // If the root is a leaf node, just remove the key if exists and return
         if let BPNode::Leaf(leaf) = root.deref_mut() {
-            if let Ok(index) = leaf.search_key(&key) {
-                leaf.remove(index).unwrap();
-            }
-            return;
+            let index = leaf.search_key(key, cmp).ok()?;
+            return leaf.remove(index).map(|(_, value)| value);
         }
 
         // The root is an index node
         let root = root.as_index_mut();
 
         // check if the key is in the tree root
-        let (exist, child_index) = root.get_index_of(key);
+        let (exist, child_index) = root.get_index_of(key, cmp);
 
-        let underflow = {
+        let removed = {
             // recursively remove the subtree root
             let child = root.get_child_mut(child_index).unwrap();
-            Self::remove_recur(child, &key);
-            child.borrow().is_underflow()
+            Self::remove_recur(child, key, cmp)
         };
+        root.fix_underflow_child(child_index, exist);
 
-        if underflow {
-            // If the child node is underflow, merge or rebalance it with its sibling node
-            // It is guaranteed that the sibling node is not empty
-            let sibling_index = root.get_sibiling_index(child_index);
-            let sibiling_is_left = sibling_index < child_index;
-            let sibling = root.get_child(sibling_index).unwrap();
-            if sibling.borrow().is_minimum() {
-                // if the sibling node is minimum, merge it with the child node
-                root.merge_children(child_index, sibiling_is_left);
-            } else {
-                // if the sibling node is not minimum, rebalance it with the child node
-                root.rebalance_children(child_index, sibiling_is_left);
-            }
-        } else if exist {
-            // Find the successor and replace the key
-            let child = root.get_child(child_index).unwrap();
-            let successor = BPNode::minimum(&child);
-            root.set_key(child_index - 1, successor);
-        }
+        removed
     }
 
-    pub(crate) fn split_node(node: &BPNodePtr<FANOUT, K, V>) -> (K, BPNodePtr<FANOUT, K, V>) {
+    pub(crate) fn split_node(node: &BPNodePtr<FANOUT, K, V, A>) -> (K, BPNodePtr<FANOUT, K, V, A>) {
         match node.borrow_mut().deref_mut() {
             BPNode::Leaf(leaf) => BPLeafNode::split_leaf_node(node, leaf),
             BPNode::Index(index) => BPIndexNode::split_node(node, index),
         }
     }
 
-    pub fn minimum(node: &BPNodePtr<FANOUT, K, V>) -> K {
+    pub fn minimum(node: &BPNodePtr<FANOUT, K, V, A>) -> K {
         let node = node.borrow();
         if let BPNode::Index(inode) = node.deref() {
-            return Self::minimum(&inode.get_child(0).unwrap());
+            return Self::minimum(inode.get_child(0).unwrap());
+        }
+        node.as_leaf().get_key(0).unwrap().clone()
+    }
+
+    pub(crate) fn search_leaf<Q: ?Sized, C: Comparator<Q>>(
+        node: &BPNodePtr<FANOUT, K, V, A>,
+        key: &Q,
+        cmp: &C,
+    ) -> BPNodePtr<FANOUT, K, V, A>
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        match node.borrow().deref() {
+            BPNode::Leaf(_) => node.clone(),
+            BPNode::Index(index) => {
+                let (_, idx) = index.get_index_of(key, cmp);
+                Self::search_leaf(index.get_child(idx).unwrap(), key, cmp)
+            }
+        }
+    }
+
+    pub(crate) fn search_path<Q: ?Sized, C: Comparator<Q>>(
+        root: &BPNodePtr<FANOUT, K, V, A>,
+        key: &Q,
+        cmp: &C,
+    ) -> (BPPath<FANOUT, K, V, A>, BPNodePtr<FANOUT, K, V, A>)
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        let mut path = Vec::new();
+        let mut node = root.clone();
+        loop {
+            let child = match node.borrow().deref() {
+                BPNode::Leaf(_) => None,
+                BPNode::Index(index) => {
+                    let (exist, idx) = index.get_index_of(key, cmp);
+                    Some((index.get_child_clone(idx).unwrap(), idx, exist))
+                }
+            };
+            match child {
+                None => return (path, node),
+                Some((child, idx, exist)) => {
+                    path.push((std::mem::replace(&mut node, child), idx, exist));
+                }
+            }
+        }
+    }
+
+    pub(crate) fn leftmost_leaf(node: &BPNodePtr<FANOUT, K, V, A>) -> BPNodePtr<FANOUT, K, V, A> {
+        match node.borrow().deref() {
+            BPNode::Leaf(_) => node.clone(),
+            BPNode::Index(index) => Self::leftmost_leaf(index.get_child(0).unwrap()),
+        }
+    }
+
+    pub(crate) fn rightmost_leaf(node: &BPNodePtr<FANOUT, K, V, A>) -> BPNodePtr<FANOUT, K, V, A> {
+        match node.borrow().deref() {
+            BPNode::Leaf(_) => node.clone(),
+            BPNode::Index(index) => {
+                Self::rightmost_leaf(index.get_children().last().unwrap())
+            }
+        }
+    }
+
+    /// The leaf holding the entry at position `index` in key order in the subtree
+    /// under `node`, along with the position of the entry in that leaf.
+    ///
+    /// Descends by the counts of the children, skipping every subtree before the
+    /// one the entry is in.
+    pub(crate) fn select(node: &BPNodePtr<FANOUT, K, V, A>, index: usize) -> Option<(BPNodePtr<FANOUT, K, V, A>, usize)> {
+        let mut node = node.clone();
+        let mut index = index;
+        loop {
+            let child = match node.borrow().deref() {
+                BPNode::Leaf(leaf) if index < leaf.len() => None,
+                BPNode::Leaf(_) => return None,
+                BPNode::Index(inode) => {
+                    let mut child_index = 0;
+                    for &count in inode.get_counts() {
+                        if index < count {
+                            break;
+                        }
+                        index -= count;
+                        child_index += 1;
+                    }
+                    Some(inode.get_child_clone(child_index)?)
+                }
+            };
+            match child {
+                None => return Some((node, index)),
+                Some(child) => node = child,
+            }
+        }
+    }
+
+    /// The number of entries in the subtree under `node` with keys less than (or,
+    /// if `inclusive` is true, less than or equal to) `key`.
+    pub(crate) fn rank<Q: ?Sized, C: Comparator<Q>>(
+        node: &BPNodePtr<FANOUT, K, V, A>,
+        key: &Q,
+        cmp: &C,
+        inclusive: bool,
+    ) -> usize
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        match node.borrow().deref() {
+            BPNode::Leaf(leaf) => match leaf.search_key(key, cmp) {
+                Ok(index) if inclusive => index + 1,
+                Ok(index) | Err(index) => index,
+            },
+            BPNode::Index(inode) => {
+                let (_, index) = inode.get_index_of(key, cmp);
+                let before: usize = inode.get_counts()[..index].iter().sum();
+                before + Self::rank(inode.get_child(index).unwrap(), key, cmp, inclusive)
+            }
+        }
+    }
+
+    /// The summary of the entries in the subtree under `node` with keys between
+    /// `lower` and `upper`.
+    ///
+    /// When the two bounds lead to different children, the children in between
+    /// add their cached summaries, and the two outer ones are descended with one
+    /// bound left open, so only two paths are ever visited.
+    pub(crate) fn aggregate<Q: ?Sized, C: Comparator<Q>>(
+        node: &BPNodePtr<FANOUT, K, V, A>,
+        lower: Bound<&Q>,
+        upper: Bound<&Q>,
+        cmp: &C,
+    ) -> A
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        match node.borrow().deref() {
+            BPNode::Leaf(leaf) => {
+                let start = match lower {
+                    Bound::Included(key) => leaf.search_key(key, cmp).unwrap_or_else(|index| index),
+                    Bound::Excluded(key) => leaf.search_key(key, cmp).map_or_else(|index| index, |index| index + 1),
+                    Bound::Unbounded => 0,
+                };
+                let end = match upper {
+                    Bound::Included(key) => leaf.search_key(key, cmp).map_or_else(|index| index, |index| index + 1),
+                    Bound::Excluded(key) => leaf.search_key(key, cmp).unwrap_or_else(|index| index),
+                    Bound::Unbounded => leaf.len(),
+                };
+                if start < end {
+                    leaf.summary_of(start..end)
+                } else {
+                    A::identity()
+                }
+            }
+            BPNode::Index(inode) => {
+                let first = match lower {
+                    Bound::Included(key) | Bound::Excluded(key) => inode.get_index_of(key, cmp).1,
+                    Bound::Unbounded => 0,
+                };
+                let last = match upper {
+                    Bound::Included(key) | Bound::Excluded(key) => inode.get_index_of(key, cmp).1,
+                    Bound::Unbounded => inode.get_children().len() - 1,
+                };
+                let child = |index| inode.get_child(index).unwrap();
+                match first.cmp(&last) {
+                    std::cmp::Ordering::Greater => A::identity(),
+                    std::cmp::Ordering::Equal => Self::aggregate(child(first), lower, upper, cmp),
+                    std::cmp::Ordering::Less => {
+                        let summary = Self::aggregate(child(first), lower, Bound::Unbounded, cmp);
+                        let summary = inode.get_summaries()[first + 1..last]
+                            .iter()
+                            .fold(summary, |summary, middle| summary.combine(middle));
+                        summary.combine(&Self::aggregate(child(last), Bound::Unbounded, upper, cmp))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Cuts the subtree under `node` in two along the path to `key`: the keys
+    /// greater than (or, if `inclusive` is false, greater than or equal to) `key`
+    /// move to the returned subtree.
+    ///
+    /// Nodes along the cut are left as they are, possibly underflowing or even
+    /// empty, for the caller to repair.
+    pub(crate) fn split_off_recur<Q: ?Sized, C: Comparator<Q>>(
+        node: &BPNodePtr<FANOUT, K, V, A>,
+        key: &Q,
+        cmp: &C,
+        inclusive: bool,
+    ) -> BPNodePtr<FANOUT, K, V, A>
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        match node.borrow_mut().deref_mut() {
+            BPNode::Leaf(leaf) => {
+                let index = match leaf.search_key(key, cmp) {
+                    Ok(index) if inclusive => index + 1,
+                    Ok(index) | Err(index) => index,
+                };
+                BPLeafNode::split_off_at(leaf, index)
+            }
+            BPNode::Index(inode) => {
+                let (_, index) = inode.get_index_of(key, cmp);
+                let child = inode.get_child_clone(index).unwrap();
+                let right = Self::split_off_recur(&child, key, cmp, inclusive);
+                BPIndexNode::split_off_at(inode, index, right)
+            }
+        }
+    }
+
+    /// Cuts the subtree under `node` in two in front of the entry at `index` in
+    /// the leaf reached by taking the children at `path`, one per level: that
+    /// entry and every one after it move to the returned subtree.
+    ///
+    /// Like [`BPNode::split_off_recur`], it leaves the nodes along the cut for the
+    /// caller to repair.
+    pub(crate) fn split_off_at_path(
+        node: &BPNodePtr<FANOUT, K, V, A>,
+        path: &[usize],
+        index: usize,
+    ) -> BPNodePtr<FANOUT, K, V, A> {
+        match node.borrow_mut().deref_mut() {
+            BPNode::Leaf(leaf) => BPLeafNode::split_off_at(leaf, index),
+            BPNode::Index(inode) => {
+                let child = inode.get_child_clone(path[0]).unwrap();
+                let right = Self::split_off_at_path(&child, &path[1..], index);
+                BPIndexNode::split_off_at(inode, path[0], right)
+            }
+        }
+    }
+
+    /// The nodes on the rightmost (or, if `last` is false, the leftmost) path from
+    /// `root` down to a leaf.
+    pub(crate) fn spine(root: &BPNodePtr<FANOUT, K, V, A>, last: bool) -> Vec<BPNodePtr<FANOUT, K, V, A>> {
+        let mut spine = vec![root.clone()];
+        loop {
+            let child = match spine.last().unwrap().borrow().deref() {
+                BPNode::Leaf(_) => None,
+                BPNode::Index(index) => {
+                    let children = index.get_children();
+                    if last {
+                        children.last().cloned()
+                    } else {
+                        children.first().cloned()
+                    }
+                }
+            };
+            match child {
+                None => return spine,
+                Some(child) => spine.push(child),
+            }
+        }
+    }
+
+    /// Repairs every underflowing node on the rightmost (or leftmost) path from
+    /// `root`, top-down, and returns whether anything changed.
+    ///
+    /// Merges can leave a node higher up underflowing again, so callers repeat
+    /// this until nothing changes.
+    pub(crate) fn repair_spine(root: &BPNodePtr<FANOUT, K, V, A>, last: bool) -> bool {
+        let mut changed = false;
+        let mut node = root.clone();
+        loop {
+            let child = match node.borrow_mut().deref_mut() {
+                BPNode::Leaf(_) => return changed,
+                BPNode::Index(index) => {
+                    let child_index = if last { index.get_children().len() - 1 } else { 0 };
+                    let (repaired, child_index) = index.repair_child(child_index);
+                    changed |= repaired;
+                    index.get_child_clone(child_index).unwrap()
+                }
+            };
+            node = child;
+        }
+    }
+
+    /// Repairs the underflowing nodes in the subtree under `node`, bottom-up,
+    /// after entries were taken out of its leaves in place, and returns whether
+    /// anything changed.
+    ///
+    /// Merging two index nodes can bring along a child that was too small to be
+    /// repaired under its old parent, so callers repeat this until nothing changes.
+    pub(crate) fn repair_recur(node: &BPNodePtr<FANOUT, K, V, A>) -> bool {
+        let mut changed = false;
+        if let BPNode::Index(index) = node.borrow_mut().deref_mut() {
+            for child_index in 0..index.get_children().len() {
+                changed |= Self::repair_recur(index.get_child(child_index).unwrap());
+                index.refresh(child_index);
+            }
+            let mut child_index = 0;
+            while child_index < index.get_children().len() {
+                let (repaired, repaired_index) = index.repair_child(child_index);
+                changed |= repaired;
+                child_index = repaired_index + 1;
+            }
         }
-        *node.as_leaf().get_key(0).unwrap()
+        changed
     }
 
-    pub(crate) fn search_recur(root: &BPNodePtr<FANOUT, K, V>, key: &K) -> Option<V> {
-        let root = root.borrow();
-        match root.deref() {
-            BPNode::Leaf(leaf) => leaf
-                .search_key(&key)
-                .ok()
-                .map(|index| leaf.get_value(index).unwrap())
-                .cloned(),
+    /// Joins two subtrees, every key of `left` being less than `separator`, the
+    /// minimum key of `right`, into one whose root is returned.
+    ///
+    /// The shorter subtree becomes the last (or first) child of the node of the
+    /// right height on the spine of the taller one, and the nodes above it are
+    /// split as needed. The attached root may underflow, for the caller to repair.
+    pub(crate) fn join(
+        left: BPNodePtr<FANOUT, K, V, A>,
+        separator: K,
+        right: BPNodePtr<FANOUT, K, V, A>,
+    ) -> BPNodePtr<FANOUT, K, V, A> {
+        let left_spine = Self::spine(&left, true);
+        let right_spine = Self::spine(&right, false);
+        // the levels the two subtrees share become continuous
+        for (l, r) in left_spine.iter().rev().zip(right_spine.iter().rev()) {
+            Self::link(l, r);
+        }
+
+        let (left_height, right_height) = (left_spine.len(), right_spine.len());
+        let (root, path) = match left_height.cmp(&right_height) {
+            std::cmp::Ordering::Equal => {
+                return BPNode::new_index_ptr_from(BPIndexNode::new_with(
+                    vec![separator],
+                    vec![left, right],
+                    None,
+                    None,
+                    None,
+                ));
+            }
+            std::cmp::Ordering::Greater => {
+                let path = &left_spine[..left_height - right_height];
+                let mut parent = path.last().unwrap().borrow_mut();
+                let parent = parent.as_index_mut();
+                parent.push_key(separator);
+                parent.push_child(right);
+                (left.clone(), path)
+            }
+            std::cmp::Ordering::Less => {
+                let path = &right_spine[..right_height - left_height];
+                let mut parent = path.last().unwrap().borrow_mut();
+                let parent = parent.as_index_mut();
+                parent.insert_key_at(0, separator);
+                parent.insert_child_at(0, left);
+                (right.clone(), path)
+            }
+        };
+        // split the nodes that overflowed, bottom-up
+        for pair in path.windows(2).rev() {
+            let mut parent = pair[0].borrow_mut();
+            let parent = parent.as_index_mut();
+            let child_index = if left_height > right_height {
+                parent.get_children().len() - 1
+            } else {
+                0
+            };
+            parent.fix_overflow_child(child_index);
+        }
+        root
+    }
+
+    /// Copies the subtree under `node` into new nodes, rather than sharing them.
+    ///
+    /// The copy has the same layout, with its own `prev`/`next` links between the
+    /// nodes of each level.
+    pub(crate) fn deep_clone(node: &BPNodePtr<FANOUT, K, V, A>) -> BPNodePtr<FANOUT, K, V, A>
+    where
+        V: Clone,
+    {
+        let mut levels = Vec::new();
+        let root = Self::clone_recur(node, 0, &mut levels);
+        for level in levels {
+            for pair in level.windows(2) {
+                Self::link(&pair[0], &pair[1]);
+            }
+        }
+        root
+    }
+
+    /// Copies the subtree under `node`, collecting the copies level by level, from
+    /// left to right.
+    fn clone_recur(
+        node: &BPNodePtr<FANOUT, K, V, A>,
+        depth: usize,
+        levels: &mut Vec<Vec<BPNodePtr<FANOUT, K, V, A>>>,
+    ) -> BPNodePtr<FANOUT, K, V, A>
+    where
+        V: Clone,
+    {
+        let copy = match node.borrow().deref() {
+            BPNode::Leaf(leaf) => BPNode::new_leaf_ptr_from(BPLeafNode::new_with(
+                leaf.get_keys().clone(),
+                leaf.get_values().clone(),
+                None,
+                None,
+                None,
+            )),
             BPNode::Index(index) => {
-                let (_, idx) = index.get_index_of(&key);
-                let child = index.get_child(idx).unwrap();
-                Self::search_recur(child, key)
+                let children = index
+                    .get_children()
+                    .iter()
+                    .map(|child| Self::clone_recur(child, depth + 1, levels))
+                    .collect();
+                BPNode::new_index_ptr_from(BPIndexNode::new_with(
+                    index.get_keys().clone(),
+                    children,
+                    None,
+                    None,
+                    None,
+                ))
+            }
+        };
+        if levels.len() <= depth {
+            levels.resize_with(depth + 1, Vec::new);
+        }
+        levels[depth].push(copy.clone());
+        copy
+    }
+
+    /// Makes `right` the next sibling of `left`, on the same level.
+    fn link(left: &BPNodePtr<FANOUT, K, V, A>, right: &BPNodePtr<FANOUT, K, V, A>) {
+        match (left.borrow_mut().deref_mut(), right.borrow_mut().deref_mut()) {
+            (BPNode::Leaf(l), BPNode::Leaf(r)) => {
+                l.next = Some(right.clone());
+                r.prev = Some(Rc::downgrade(left));
+            }
+            (BPNode::Index(l), BPNode::Index(r)) => {
+                l.next = Some(right.clone());
+                r.prev = Some(Rc::downgrade(left));
+            }
+            _ => unreachable!("siblings are on the same level"),
+        }
+    }
+
+    /// Builds a tree bottom-up from entries sorted by key: packs the leaves and
+    /// links them, then packs each index level over the one below it.
+    ///
+    /// Nodes are filled to `fill_factor` of their capacity, and the entries are
+    /// spread evenly so that no node but the root ends up under its minimum.
+    pub(crate) fn from_sorted_entries(entries: Vec<(K, V)>, fill_factor: f64) -> BPNodePtr<FANOUT, K, V, A> {
+        // every node of the current level, with the minimum key of its subtree
+        let mut level = Vec::new();
+        let mut entries = entries.into_iter();
+        let mut prev: Option<BPNodePtr<FANOUT, K, V, A>> = None;
+        for size in Self::chunk_sizes(entries.len(), FANOUT / 2, FANOUT - 1, fill_factor) {
+            let (keys, values): (Vec<K>, Vec<V>) = entries.by_ref().take(size).unzip();
+            let min = keys[0].clone();
+            let leaf = BPNode::new_leaf_ptr_from(BPLeafNode::new_with(
+                keys,
+                values,
+                None,
+                prev.as_ref().map(Rc::downgrade),
+                None,
+            ));
+            if let Some(prev) = prev.replace(leaf.clone()) {
+                prev.borrow_mut().as_leaf_mut().next = Some(leaf.clone());
+            }
+            level.push((min, leaf));
+        }
+        if level.is_empty() {
+            return BPNode::new_leaf_ptr();
+        }
+
+        while level.len() > 1 {
+            let sizes = Self::chunk_sizes(level.len(), FANOUT.div_ceil(2), FANOUT, fill_factor);
+            let mut nodes = level.into_iter();
+            let mut upper = Vec::new();
+            let mut prev: Option<BPNodePtr<FANOUT, K, V, A>> = None;
+            for size in sizes {
+                let (min, first) = nodes.next().unwrap();
+                // the minimum of every child but the first separates it from the one before
+                let (keys, mut children): (Vec<K>, Vec<_>) = nodes.by_ref().take(size - 1).unzip();
+                children.insert(0, first);
+                let index = BPNode::new_index_ptr_from(BPIndexNode::new_with(
+                    keys,
+                    children,
+                    None,
+                    prev.as_ref().map(Rc::downgrade),
+                    None,
+                ));
+                if let Some(prev) = prev.replace(index.clone()) {
+                    prev.borrow_mut().as_index_mut().next = Some(index.clone());
+                }
+                upper.push((min, index));
+            }
+            level = upper;
+        }
+        level.pop().unwrap().1
+    }
+
+    /// Splits `len` items into nodes of `fill_factor` of `max` items each, as
+    /// evenly as possible and with at least `min` items in each node.
+    fn chunk_sizes(len: usize, min: usize, max: usize, fill_factor: f64) -> impl Iterator<Item = usize> {
+        let min = min.max(1);
+        let target = ((max as f64 * fill_factor).round() as usize).clamp(min, max);
+        let count = len.div_ceil(target).min(len / min).max(1);
+        (0..count).map(move |i| len / count + usize::from(i < len % count)).filter(|&size| size > 0)
+    }
+
+    /// Checks the subtree under `node` against the invariants of the tree, and
+    /// returns the number of entries in it.
+    ///
+    /// `path` holds the indices of the children taken down to `node`, and its keys
+    /// must fall in `lower..upper` of the separators above it. Every node is
+    /// recorded in `levels` by depth, left to right, so that the sibling links can
+    /// be checked level by level afterwards with [`BPNode::validate_links`].
+    pub(crate) fn validate_recur<C: Comparator<K>>(
+        node: &BPNodePtr<FANOUT, K, V, A>,
+        path: &mut Vec<usize>,
+        lower: Option<&K>,
+        upper: Option<&K>,
+        cmp: &C,
+        leaf_depth: &mut Option<usize>,
+        levels: &mut Vec<BPLevel<FANOUT, K, V, A>>,
+    ) -> Result<usize, InvariantViolation> {
+        let violation = |path: &[usize], kind| InvariantViolation {
+            path: path.to_vec(),
+            kind,
+        };
+        let depth = path.len();
+        if levels.len() == depth {
+            levels.push(Vec::new());
+        }
+        levels[depth].push((node.clone(), path.clone()));
+        let is_root = depth == 0;
+        match node.borrow().deref() {
+            BPNode::Leaf(leaf) => {
+                if *leaf_depth.get_or_insert(depth) != depth {
+                    return Err(violation(path, ViolationKind::Depth {
+                        depth,
+                        expected: leaf_depth.unwrap(),
+                    }));
+                }
+                let min = if is_root { 0 } else { FANOUT / 2 };
+                if leaf.len() < min || leaf.len() > FANOUT - 1 {
+                    return Err(violation(path, ViolationKind::Occupancy {
+                        len: leaf.len(),
+                        min,
+                        max: FANOUT - 1,
+                    }));
+                }
+                Self::validate_keys(leaf.get_keys(), lower, upper, cmp).map_err(|kind| violation(path, kind))?;
+                Ok(leaf.len())
+            }
+            BPNode::Index(inode) => {
+                if leaf_depth.is_some_and(|leaf_depth| leaf_depth <= depth) {
+                    return Err(violation(path, ViolationKind::Depth {
+                        depth: depth + 1,
+                        expected: leaf_depth.unwrap(),
+                    }));
+                }
+                let children = inode.get_children().len();
+                let min = if is_root { 2 } else { FANOUT.div_ceil(2) };
+                if children < min || children > FANOUT {
+                    return Err(violation(path, ViolationKind::Occupancy {
+                        len: children,
+                        min,
+                        max: FANOUT,
+                    }));
+                }
+                let keys = inode.get_keys();
+                let (counts, summaries) = (inode.get_counts(), inode.get_summaries());
+                if keys.len() + 1 != children || counts.len() != children || summaries.len() != children {
+                    return Err(violation(path, ViolationKind::Shape {
+                        keys: keys.len(),
+                        children,
+                        counts: counts.len(),
+                        summaries: summaries.len(),
+                    }));
+                }
+                Self::validate_keys(keys, lower, upper, cmp).map_err(|kind| violation(path, kind))?;
+                let mut total = 0;
+                for (index, child) in inode.get_children().iter().enumerate() {
+                    let lower = if index == 0 { lower } else { keys.get(index - 1) };
+                    let upper = keys.get(index).or(upper);
+                    path.push(index);
+                    let actual = Self::validate_recur(child, path, lower, upper, cmp, leaf_depth, levels)?;
+                    path.pop();
+                    if counts[index] != actual {
+                        return Err(violation(path, ViolationKind::Count {
+                            index,
+                            cached: counts[index],
+                            actual,
+                        }));
+                    }
+                    total += actual;
+                }
+                Ok(total)
             }
         }
     }
+
+    /// Checks that `keys` are strictly increasing and fall in `lower..upper`.
+    fn validate_keys<C: Comparator<K>>(
+        keys: &[K],
+        lower: Option<&K>,
+        upper: Option<&K>,
+        cmp: &C,
+    ) -> Result<(), ViolationKind> {
+        for (index, key) in keys.iter().enumerate() {
+            if index > 0 && cmp.compare(&keys[index - 1], key).is_ge() {
+                return Err(ViolationKind::Unsorted { index });
+            }
+            let below = lower.is_some_and(|lower| cmp.compare(key, lower).is_lt());
+            let above = upper.is_some_and(|upper| cmp.compare(key, upper).is_ge());
+            if below || above {
+                return Err(ViolationKind::Separator { index });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the nodes of one level, left to right, are chained by their
+    /// `next` and `prev` links, and that the chain ends at both sides.
+    pub(crate) fn validate_links(level: &BPLevel<FANOUT, K, V, A>) -> Result<(), InvariantViolation> {
+        for (position, (node, path)) in level.iter().enumerate() {
+            let node = node.borrow();
+            let (prev, next) = match node.deref() {
+                BPNode::Leaf(leaf) => (&leaf.prev, &leaf.next),
+                BPNode::Index(index) => (&index.prev, &index.next),
+            };
+            let prev = prev.as_ref().and_then(Weak::upgrade);
+            let expected_prev = position.checked_sub(1).map(|position| &level[position].0);
+            let expected_next = level.get(position + 1).map(|(next, _)| next);
+            let linked = |link: Option<&BPNodePtr<FANOUT, K, V, A>>, expected: Option<&BPNodePtr<FANOUT, K, V, A>>| {
+                match (link, expected) {
+                    (None, None) => true,
+                    (Some(link), Some(expected)) => Rc::ptr_eq(link, expected),
+                    _ => false,
+                }
+            };
+            let kind = if !linked(next.as_ref(), expected_next) {
+                ViolationKind::NextLink
+            } else if !linked(prev.as_ref(), expected_prev) {
+                ViolationKind::PrevLink
+            } else {
+                continue;
+            };
+            return Err(InvariantViolation {
+                path: path.clone(),
+                kind,
+            });
+        }
+        Ok(())
+    }
+
+    /// Borrows the node without going through the `RefCell`, for a lifetime that is
+    /// not tied to a `Ref` guard.
+    ///
+    /// The iterators and cursors hand out references into the leaves that live as
+    /// long as their borrow of the tree, which a `Ref` guard held in the iterator
+    /// could not express, so the borrow flag of the `RefCell` is not consulted.
+    ///
+    /// # Safety
+    ///
+    /// No `&mut` to the node may exist while the result is alive, whether it comes
+    /// from [`BPNode::deref_unguarded_mut`] or from a `RefMut` guard. Nodes are only
+    /// ever mutated through `&mut BPTree`, so this holds as long as `'a` does not
+    /// outlive a shared borrow of the tree that owns `node`.
+    pub(crate) unsafe fn deref_unguarded<'a>(node: &BPNodePtr<FANOUT, K, V, A>) -> &'a Self {
+        &*node.as_ptr()
+    }
+
+    /// Mutable counterpart of [`BPNode::deref_unguarded`].
+    ///
+    /// # Safety
+    ///
+    /// No other reference to the node may exist while the result is alive, whether
+    /// it comes from [`BPNode::deref_unguarded`], from this function or from a
+    /// `RefCell` guard. This holds as long as `'a` does not outlive an exclusive
+    /// borrow of the tree that owns `node`, and nothing else is derived from the
+    /// node during `'a`.
+    pub(crate) unsafe fn deref_unguarded_mut<'a>(node: &BPNodePtr<FANOUT, K, V, A>) -> &'a mut Self {
+        &mut *node.as_ptr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bp_tree::BPTree;
+
+    type Tree = BPTree<4, u32, u32>;
+
+    /// A tree with two index nodes under the root, each over three full leaves:
+    /// `[0, 10, 20] [30, 40, 50] [60, 70, 80]` under the first, with separators
+    /// `[30, 60]`, and `[90, 100, 110] [120, 130, 140] [150, 160, 170]` under the
+    /// second, with separators `[120, 150]`. The root separator is `90`.
+    fn tree() -> Tree {
+        let bptree = Tree::from_sorted_iter((0..18).map(|i| (i * 10, i)), 1.0).unwrap();
+        bptree.validate().unwrap();
+        bptree
+    }
+
+    fn node_at(bptree: &Tree, path: &[usize]) -> BPNodePtr<4, u32, u32, ()> {
+        path.iter().fold(bptree.root.clone(), |node, &index| {
+            node.borrow().as_index().get_child_clone(index).unwrap()
+        })
+    }
+
+    fn violation(bptree: &Tree) -> (ViolationKind, Vec<usize>) {
+        let violation = bptree.validate().unwrap_err();
+        (violation.kind, violation.path)
+    }
+
+    #[test]
+    fn validate_finds_separator() {
+        let bptree = tree();
+        // the first key under the second index node now falls below the root
+        // separator
+        node_at(&bptree, &[]).borrow_mut().as_index_mut().set_key(0, 125);
+        assert_eq!(
+            violation(&bptree),
+            (ViolationKind::Separator { index: 0 }, vec![1])
+        );
+    }
+
+    #[test]
+    fn validate_finds_count() {
+        let bptree = tree();
+        node_at(&bptree, &[1]).borrow_mut().as_index_mut().counts_mut()[2] += 1;
+        assert_eq!(
+            violation(&bptree),
+            (
+                ViolationKind::Count {
+                    index: 2,
+                    cached: 4,
+                    actual: 3,
+                },
+                vec![1]
+            )
+        );
+    }
+
+    #[test]
+    fn validate_finds_next_link() {
+        let bptree = tree();
+        node_at(&bptree, &[0, 2]).borrow_mut().as_leaf_mut().next = None;
+        assert_eq!(violation(&bptree), (ViolationKind::NextLink, vec![0, 2]));
+    }
+
+    #[test]
+    fn validate_finds_prev_link() {
+        let bptree = tree();
+        node_at(&bptree, &[1, 0]).borrow_mut().as_leaf_mut().prev = None;
+        assert_eq!(violation(&bptree), (ViolationKind::PrevLink, vec![1, 0]));
+    }
+
+    #[test]
+    fn validate_finds_occupancy() {
+        let bptree = tree();
+        node_at(&bptree, &[1, 1])
+            .borrow_mut()
+            .as_leaf_mut()
+            .insert_key_value(1, 125, 0);
+        assert_eq!(
+            violation(&bptree),
+            (
+                ViolationKind::Occupancy {
+                    len: 4,
+                    min: 2,
+                    max: 3,
+                },
+                vec![1, 1]
+            )
+        );
+    }
+
+    #[test]
+    fn validate_finds_depth() {
+        let bptree = tree();
+        // the first leaf under the second index node takes its place
+        let leaf = node_at(&bptree, &[1, 0]);
+        *bptree.root.borrow_mut().as_index_mut().get_child_mut(1).unwrap() = leaf;
+        assert_eq!(
+            violation(&bptree),
+            (
+                ViolationKind::Depth {
+                    depth: 1,
+                    expected: 2,
+                },
+                vec![1]
+            )
+        );
+    }
+
+    #[test]
+    fn validate_finds_length() {
+        let mut bptree = tree();
+        bptree.length += 1;
+        assert_eq!(
+            violation(&bptree),
+            (
+                ViolationKind::Length {
+                    cached: 19,
+                    actual: 18,
+                },
+                Vec::new()
+            )
+        );
+    }
 }
@@ -0,0 +1,193 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
+use std::ops::RangeBounds;
+
+use common::{check_same, Lcg};
+
+use rust_bplus_tree::bp_aggregate::{Aggregate, Max, Min, Sum};
+use rust_bplus_tree::bp_comparator::NaturalOrder;
+use rust_bplus_tree::bp_tree::BPTree;
+
+/// The keys in order, to catch summaries combined in the wrong order.
+#[derive(Debug, Clone, PartialEq)]
+struct KeyList(Vec<u32>);
+
+impl<V> Aggregate<u32, V> for KeyList {
+    fn identity() -> Self {
+        KeyList(Vec::new())
+    }
+
+    fn from_entry(key: &u32, _: &V) -> Self {
+        KeyList(vec![*key])
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        KeyList([&self.0[..], &other.0[..]].concat())
+    }
+}
+
+type Stats = (Sum<u64>, (Min<u64>, Max<u64>));
+type StatsTree<const FANOUT: usize> = BPTree<FANOUT, u32, u64, NaturalOrder, Stats>;
+
+fn expected_stats(expected: &BTreeMap<u32, u64>, range: impl RangeBounds<u32>) -> Stats {
+    let values: Vec<u64> = expected.range(range).map(|(_, value)| *value).collect();
+    (
+        Sum(values.iter().sum()),
+        (
+            Min(values.iter().min().copied()),
+            Max(values.iter().max().copied()),
+        ),
+    )
+}
+
+fn check_stats<const FANOUT: usize>(bptree: &StatsTree<FANOUT>, expected: &BTreeMap<u32, u64>) {
+    check_same(bptree, expected);
+    assert_eq!(bptree.aggregate::<u32, _>(..), expected_stats(expected, ..));
+    let max = expected.keys().last().map_or(0, |key| key + 2);
+    for start in (0..max).step_by(13) {
+        for end in (start..max + 3).step_by(11) {
+            let ranges: [(Bound<u32>, Bound<u32>); 3] = [
+                (Included(start), Excluded(end)),
+                (Excluded(start), Included(end)),
+                (Included(start), Unbounded),
+            ];
+            for range in ranges {
+                assert_eq!(bptree.aggregate(range), expected_stats(expected, range));
+            }
+        }
+    }
+}
+
+fn check_random<const FANOUT: usize>(n: u32) {
+    let mut bptree = StatsTree::<FANOUT>::new();
+    let mut expected = BTreeMap::new();
+    let mut rng = Lcg(n);
+    for round in 0..4 {
+        for _ in 0..n {
+            let seed = rng.next();
+            let key = (seed >> 16) % (n * 2);
+            let value = u64::from(seed % 1000);
+            if seed % 4 >= round {
+                assert_eq!(bptree.insert(key, value), expected.insert(key, value));
+            } else {
+                assert_eq!(bptree.remove(&key), expected.remove(&key));
+            }
+        }
+        check_stats(&bptree, &expected);
+    }
+}
+
+#[test]
+fn aggregate_random_test() {
+    for n in [1, 2, 10, 100, 300] {
+        check_random::<3>(n);
+        check_random::<4>(n);
+        check_random::<5>(n);
+        check_random::<8>(n);
+    }
+}
+
+fn check_bulk_operations<const FANOUT: usize>() {
+    let entries = (0..300).map(|i| (i * 2, u64::from(i * 37 % 101)));
+    let mut bptree = StatsTree::<FANOUT>::from_sorted_iter(entries.clone(), 0.7).unwrap();
+    let mut expected: BTreeMap<u32, u64> = entries.collect();
+    check_stats(&bptree, &expected);
+    check_stats(&bptree.clone(), &expected);
+
+    if let Some(mut entry) = bptree.first_entry() {
+        entry.insert(1000);
+    }
+    expected.insert(0, 1000);
+    check_stats(&bptree, &expected);
+    assert_eq!(bptree.pop_last(), expected.pop_last());
+    check_stats(&bptree, &expected);
+
+    let mut right = bptree.split_off(&301);
+    let mut expected_right = expected.split_off(&301);
+    check_stats(&bptree, &expected);
+    check_stats(&right, &expected_right);
+
+    right.remove_range(400..500);
+    expected_right.retain(|key, _| !(400..500).contains(key));
+    check_stats(&right, &expected_right);
+
+    bptree.append(&mut right);
+    expected.append(&mut expected_right);
+    check_stats(&bptree, &expected);
+
+    // the predicate may change the values it keeps
+    bptree.retain(|key, value| {
+        *value += 1;
+        key % 3 != 0
+    });
+    expected.retain(|key, value| {
+        *value += 1;
+        key % 3 != 0
+    });
+    check_stats(&bptree, &expected);
+
+    let extracted: Vec<_> = bptree.extract_if(|key, _| key % 5 == 0).take(10).collect();
+    for (key, _) in extracted {
+        expected.remove(&key);
+    }
+    check_stats(&bptree, &expected);
+
+    let collected: StatsTree<FANOUT> = expected.iter().map(|(key, value)| (*key, *value)).collect();
+    check_stats(&collected, &expected);
+}
+
+#[test]
+fn aggregate_bulk_operations_test() {
+    check_bulk_operations::<3>();
+    check_bulk_operations::<4>();
+    check_bulk_operations::<5>();
+    check_bulk_operations::<8>();
+}
+
+#[test]
+fn aggregate_order_test() {
+    let mut bptree = BPTree::<4, u32, (), NaturalOrder, KeyList>::new();
+    for i in 0..200 {
+        bptree.insert(i * 7 % 200, ());
+    }
+    assert_eq!(bptree.aggregate(..), KeyList((0..200).collect()));
+    assert_eq!(bptree.aggregate(17..=150), KeyList((17..=150).collect()));
+    assert_eq!(bptree.aggregate(190..), KeyList((190..200).collect()));
+    #[allow(clippy::reversed_empty_ranges)]
+    let inverted = 60..50;
+    assert_eq!(bptree.aggregate(inverted), KeyList(Vec::new()));
+}
+
+#[test]
+fn aggregate_empty_test() {
+    let bptree = StatsTree::<4>::new();
+    assert_eq!(
+        bptree.aggregate::<u32, _>(..),
+        (Sum(0), (Min(None), Max(None)))
+    );
+    assert_eq!(bptree.aggregate(3..10), (Sum(0), (Min(None), Max(None))));
+}
+
+#[test]
+fn aggregate_update_test() {
+    let mut bptree = StatsTree::<4>::new();
+    let mut expected = BTreeMap::new();
+    for key in 0..300 {
+        bptree.insert(key, u64::from(key));
+        expected.insert(key, u64::from(key));
+    }
+    for key in (0..400).step_by(7) {
+        let updated = bptree.update(&key, |value| {
+            *value = *value * 3 + 1;
+            *value
+        });
+        let value = expected.get_mut(&key).map(|value| {
+            *value = *value * 3 + 1;
+            *value
+        });
+        assert_eq!(updated, value);
+    }
+    check_stats(&bptree, &expected);
+}
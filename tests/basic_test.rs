@@ -1,5 +1,6 @@
 use rust_bplus_tree::bp_tree::BPTree;
 
+use std::ops::Bound;
 use std::thread;
 use std::time::Duration;
 use std::sync::mpsc;
@@ -150,3 +151,242 @@ fn remove_test2() {
     println!("{:?}", bptree);
 }
  
+
+#[test]
+fn insert_replace_test() {
+    let mut bptree = BPTree::<3, u32, u32>::new();
+
+    for i in 0..20 {
+        assert_eq!(bptree.insert(i, i), None);
+    }
+    assert_eq!(bptree.insert(7, 70), Some(7));
+    assert_eq!(bptree.insert(7, 700), Some(70));
+    assert_eq!(bptree.search(&7), Some(700));
+}
+
+#[test]
+fn try_insert_test() {
+    let mut bptree = BPTree::<3, u32, u32>::new();
+
+    assert!(bptree.try_insert(1, 10).is_ok());
+    let err = bptree.try_insert(1, 20).unwrap_err();
+    assert_eq!((err.key, err.value, *err.existing), (1, 20, 10));
+    assert_eq!(bptree.search(&1), Some(10));
+}
+
+#[test]
+fn remove_return_test() {
+    let mut bptree = BPTree::<4, u32, u32>::new();
+
+    for i in 0..100 {
+        bptree.insert(i, i * 2);
+    }
+    for i in (0..100).step_by(3) {
+        assert_eq!(bptree.remove(&i), Some(i * 2));
+        assert_eq!(bptree.remove(&i), None);
+    }
+    assert!(bptree.keys().copied().eq((0..100).filter(|i| i % 3 != 0)));
+
+    for i in 0..100 {
+        bptree.remove(&i);
+    }
+    assert_eq!(bptree.iter().next(), None);
+    assert_eq!(bptree.remove(&0), None);
+}
+
+#[derive(Debug, PartialEq)]
+struct Record {
+    id: u32,
+    payload: Vec<u8>,
+}
+
+#[test]
+fn get_without_clone_test() {
+    let mut bptree = BPTree::<4, u32, Record>::new();
+    for i in 0..100 {
+        bptree.insert(i, Record { id: i, payload: vec![0; i as usize] });
+    }
+
+    let record = bptree.get(&42).unwrap();
+    assert_eq!(record.id, 42);
+    assert_eq!(record.payload.len(), 42);
+    assert_eq!(bptree.get_key_value(&7).map(|(k, v)| (*k, v.id)), Some((7, 7)));
+    assert!(bptree.contains_key(&99));
+    assert!(bptree.get(&100).is_none());
+    assert_eq!(bptree.remove(&3), Some(Record { id: 3, payload: vec![0; 3] }));
+}
+
+#[test]
+fn len_test() {
+    let mut bptree = BPTree::<3, u32, u32>::new();
+    assert!(bptree.is_empty());
+
+    // every insert splits or redistributes nodes along the way
+    for i in 0..200 {
+        bptree.insert(i, i);
+        assert_eq!(bptree.len(), i as usize + 1);
+    }
+    bptree.insert(5, 50);
+    assert!(bptree.try_insert(6, 60).is_err());
+    *bptree.entry(200).or_insert(0) += 1;
+    assert_eq!(bptree.len(), 201);
+    assert_eq!(bptree.iter().len(), 201);
+
+    // every remove merges or rebalances nodes along the way
+    for i in (0..200).rev().step_by(2) {
+        assert!(bptree.remove(&i).is_some());
+        assert!(bptree.remove(&i).is_none());
+    }
+    assert_eq!(bptree.len(), 101);
+    assert_eq!(bptree.keys().count(), 101);
+
+    bptree.clear();
+    assert!(bptree.is_empty());
+    assert_eq!(bptree.iter().next(), None);
+    bptree.insert(1, 1);
+    assert_eq!(bptree.len(), 1);
+}
+
+#[test]
+fn borrowed_key_test() {
+    let mut bptree = BPTree::<3, &'static str, u32>::new();
+    for (i, word) in ["pear", "apple", "fig", "kiwi", "plum", "lime", "date"].iter().enumerate() {
+        bptree.insert(word, i as u32);
+    }
+
+    // look up by `str` rather than by `&str`
+    let fig: &str = "fig";
+    assert_eq!(bptree.get(fig), Some(&2));
+    assert!(bptree.contains_key("kiwi"));
+    *bptree.get_mut("lime").unwrap() += 10;
+    assert_eq!(bptree.search("lime"), Some(15));
+    assert_eq!(bptree.remove("pear"), Some(0));
+    assert!(!bptree.contains_key("pear"));
+    let range: (Bound<&str>, Bound<&str>) = (Bound::Included("d"), Bound::Excluded("l"));
+    assert!(bptree.range::<str, _>(range).map(|(k, _)| *k).eq(["date", "fig", "kiwi"]));
+}
+
+#[test]
+fn string_key_test() {
+    let mut bptree = BPTree::<4, String, usize>::new();
+    for i in 0..200 {
+        bptree.insert(format!("key{:03}", (i * 7) % 200), i);
+    }
+    assert_eq!(bptree.len(), 200);
+    assert!(bptree.keys().cloned().eq((0..200).map(|i| format!("key{:03}", i))));
+
+    assert_eq!(bptree.get("key014"), Some(&2));
+    for i in (0..200).step_by(2) {
+        assert!(bptree.remove(format!("key{:03}", i).as_str()).is_some());
+    }
+    assert_eq!(bptree.first_key_value().map(|(k, _)| k.as_str()), Some("key001"));
+    *bptree.entry("key001".to_string()).or_insert(0) += 1000;
+    assert_eq!(bptree.get("key001"), Some(&1143));
+}
+
+#[test]
+fn byte_string_key_test() {
+    use std::sync::Arc;
+
+    let mut bytes = BPTree::<3, Vec<u8>, u32>::new();
+    let mut shared = BPTree::<3, Arc<str>, u32>::new();
+    for i in 0..50u32 {
+        bytes.insert(i.to_be_bytes().to_vec(), i);
+        shared.insert(Arc::from(format!("{:02}", i)), i);
+    }
+
+    assert_eq!(bytes.get(&[0u8, 0, 0, 42][..]), Some(&42));
+    assert_eq!(shared.get("17"), Some(&17));
+    assert!(bytes.values().copied().eq(0..50));
+    assert!(shared.values().copied().eq(0..50));
+}
+
+#[test]
+fn non_debug_key_value_test() {
+    #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+    struct Id(u32);
+    struct Callback(Box<dyn Fn(u32) -> u32>);
+
+    let mut bptree = BPTree::<3, Id, Callback>::new();
+    for i in 0..30 {
+        bptree.insert(Id(i), Callback(Box::new(move |x| x + i)));
+    }
+    assert!(bptree.remove(&Id(3)).is_some());
+    assert_eq!(bptree.len(), 29);
+    assert_eq!(bptree.get(&Id(10)).map(|f| (f.0)(1)), Some(11));
+    assert!(bptree.keys().map(|id| id.0).eq((0..30).filter(|&i| i != 3)));
+}
+
+#[test]
+fn deep_clone_test() {
+    let mut bptree = BPTree::<3, u32, Vec<u32>>::new();
+    for i in 0..200 {
+        bptree.insert((i * 31) % 200, vec![i]);
+    }
+
+    let mut copy = bptree.clone();
+    assert!(copy.iter().eq(bptree.iter()));
+    assert!(copy.iter().rev().eq(bptree.iter().rev()));
+
+    // the copy shares no nodes with the original
+    for i in (0..200).step_by(2) {
+        copy.remove(&i);
+    }
+    copy.get_mut(&1).unwrap().push(1000);
+    for i in 200..300 {
+        copy.insert(i, Vec::new());
+    }
+    assert_eq!(bptree.len(), 200);
+    assert!(bptree.keys().copied().eq(0..200));
+    assert_eq!(bptree.get(&1).map(Vec::len), Some(1));
+    assert_eq!(copy.len(), 200);
+    assert!(copy
+        .keys()
+        .copied()
+        .eq((1..200).step_by(2).chain(200..300)));
+    assert!(copy
+        .keys()
+        .rev()
+        .copied()
+        .eq((200..300).rev().chain((1..200).step_by(2).rev())));
+}
+
+#[test]
+fn content_comparison_test() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn hash_of<T: Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // the same entries in trees of different shapes
+    let mut inserted = BPTree::<4, u32, u32>::new();
+    for i in (0..500).rev() {
+        inserted.insert(i, i * 2);
+    }
+    let loaded =
+        BPTree::<4, u32, u32>::from_sorted_iter((0..500).map(|i| (i, i * 2)), 0.6).unwrap();
+    let mut shrunk = BPTree::<4, u32, u32>::new();
+    for i in 0..1000 {
+        shrunk.insert(i, i * 2);
+    }
+    for i in 500..1000 {
+        shrunk.remove(&i);
+    }
+
+    assert!(inserted == loaded && loaded == shrunk);
+    assert_eq!(hash_of(&inserted), hash_of(&loaded));
+    assert_eq!(hash_of(&loaded), hash_of(&shrunk));
+    assert_eq!(inserted.cmp(&shrunk), std::cmp::Ordering::Equal);
+
+    shrunk.insert(250, 0);
+    assert!(shrunk != inserted);
+    assert!(shrunk < inserted);
+    shrunk.insert(250, 500);
+    shrunk.insert(1000, 0);
+    assert!(shrunk > inserted);
+    assert!(BPTree::<4, u32, u32>::new() < inserted);
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use common::{check_same, Lcg};
+
+use rust_bplus_tree::bp_comparator::ReverseOrder;
+use rust_bplus_tree::bp_tree::BPTree;
+
+fn check_bulk<const FANOUT: usize>(n: u32, fill_factor: f64) {
+    let mut bptree =
+        BPTree::<FANOUT, u32, u32>::from_sorted_iter((0..n).map(|i| (i * 2, i)), fill_factor)
+            .unwrap();
+    let mut expected: BTreeMap<u32, u32> = (0..n).map(|i| (i * 2, i)).collect();
+    assert_eq!(bptree.len(), n as usize);
+    check_same(&bptree, &expected);
+    assert_eq!(bptree.get(&(n / 2 * 2)), expected.get(&(n / 2 * 2)));
+
+    // the loaded tree keeps working under inserts and removes
+    let mut rng = Lcg(7);
+    for _ in 0..n * 2 {
+        let seed = rng.next();
+        let key = (seed >> 16) % (n * 2 + 10);
+        if seed & 1 == 0 {
+            assert_eq!(bptree.insert(key, seed), expected.insert(key, seed));
+        } else {
+            assert_eq!(bptree.remove(&key), expected.remove(&key));
+        }
+    }
+    check_same(&bptree, &expected);
+}
+
+#[test]
+fn bulk_load_test() {
+    for n in [0, 1, 2, 3, 5, 17, 100, 1000] {
+        for fill_factor in [0.1, 0.5, 0.7, 1.0] {
+            check_bulk::<3>(n, fill_factor);
+            check_bulk::<4>(n, fill_factor);
+            check_bulk::<5>(n, fill_factor);
+            check_bulk::<8>(n, fill_factor);
+        }
+    }
+}
+
+#[test]
+fn bulk_load_range_test() {
+    let bptree = BPTree::<6, u32, u32>::from_sorted_iter((0..10_000).map(|i| (i, i)), 1.0).unwrap();
+    assert!(bptree.range(4321..4400).map(|(k, _)| *k).eq(4321..4400));
+    assert_eq!(bptree.first_key_value(), Some((&0, &0)));
+    assert_eq!(bptree.last_key_value(), Some((&9999, &9999)));
+}
+
+#[test]
+fn bulk_load_unsorted_test() {
+    let error = BPTree::<4, u32, ()>::from_sorted_iter([(1, ()), (3, ()), (2, ()), (0, ())], 1.0)
+        .unwrap_err();
+    assert_eq!((error.key, error.position), (2, 2));
+
+    let error = BPTree::<4, u32, ()>::from_sorted_iter([(1, ()), (1, ())], 1.0).unwrap_err();
+    assert_eq!((error.key, error.position), (1, 1));
+    assert_eq!(
+        error.to_string(),
+        "key 1 at position 1 is not greater than the key before it"
+    );
+}
+
+#[test]
+#[should_panic(expected = "fill factor must be in (0, 1]")]
+fn bulk_load_fill_factor_test() {
+    let _ = BPTree::<4, u32, ()>::from_sorted_iter([(1, ())], 0.0);
+}
+
+#[test]
+fn bulk_load_comparator_test() {
+    let mut bptree = BPTree::<4, u32, u32, _>::from_sorted_iter_with_comparator(
+        (0..500).rev().map(|i| (i, i)),
+        0.8,
+        ReverseOrder,
+    )
+    .unwrap();
+    assert!(bptree.keys().copied().eq((0..500).rev()));
+    assert_eq!(bptree.remove(&250), Some(250));
+    assert_eq!(bptree.insert(250, 0), None);
+    assert!(bptree.keys().copied().eq((0..500).rev()));
+
+    assert!(BPTree::<4, u32, u32, _>::from_sorted_iter_with_comparator(
+        (0..10).map(|i| (i, i)),
+        1.0,
+        ReverseOrder
+    )
+    .is_err());
+}
@@ -0,0 +1,31 @@
+//! Helpers shared by the integration tests, which check a `BPTree` against a
+//! `BTreeMap` holding the same entries.
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+use rust_bplus_tree::bp_aggregate::Aggregate;
+use rust_bplus_tree::bp_comparator::NaturalOrder;
+use rust_bplus_tree::bp_tree::BPTree;
+
+/// A linear congruential generator, so that the random tests are repeatable.
+pub struct Lcg(pub u32);
+
+impl Lcg {
+    /// Steps the generator, and returns its new state.
+    pub fn next(&mut self) -> u32 {
+        self.0 = self.0.wrapping_mul(1103515245).wrapping_add(12345);
+        self.0
+    }
+}
+
+/// Checks that the tree holds the same entries as `expected`, walked either way.
+pub fn check_same<const FANOUT: usize, V: PartialEq + Debug, A: Aggregate<u32, V>>(
+    bptree: &BPTree<FANOUT, u32, V, NaturalOrder, A>,
+    expected: &BTreeMap<u32, V>,
+) {
+    assert_eq!(bptree.len(), expected.len());
+    assert!(bptree.iter().eq(expected.iter()));
+    assert!(bptree.iter().rev().eq(expected.iter().rev()));
+}
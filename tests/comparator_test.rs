@@ -0,0 +1,100 @@
+use std::cmp::Ordering;
+use std::ops::Bound;
+
+use rust_bplus_tree::bp_comparator::{Comparator, ReverseOrder};
+use rust_bplus_tree::bp_tree::BPTree;
+
+#[derive(Default)]
+struct CaseInsensitive;
+
+impl<T: AsRef<str> + ?Sized> Comparator<T> for CaseInsensitive {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        let a = a.as_ref().chars().flat_map(char::to_lowercase);
+        let b = b.as_ref().chars().flat_map(char::to_lowercase);
+        a.cmp(b)
+    }
+}
+
+#[test]
+fn case_insensitive_test() {
+    let mut bptree = BPTree::<3, String, u32, _>::with_comparator(CaseInsensitive);
+    for (i, word) in ["pear", "Apple", "banana", "Cherry", "date", "Fig", "grape"]
+        .iter()
+        .enumerate()
+    {
+        bptree.insert(word.to_string(), i as u32);
+    }
+
+    assert!(bptree
+        .keys()
+        .map(String::as_str)
+        .eq(["Apple", "banana", "Cherry", "date", "Fig", "grape", "pear"]));
+    assert_eq!(bptree.get("APPLE"), Some(&1));
+    assert_eq!(bptree.get("cherry"), Some(&3));
+
+    // keys equal under the comparator are the same key
+    assert_eq!(bptree.insert("PEAR".to_string(), 10), Some(0));
+    assert_eq!(bptree.len(), 7);
+    assert_eq!(
+        bptree.get_key_value("pear").map(|(k, _)| k.as_str()),
+        Some("pear")
+    );
+    assert_eq!(bptree.remove("fig"), Some(5));
+    assert!(bptree
+        .range::<str, _>((Bound::Included("B"), Bound::Excluded("E")))
+        .map(|(k, _)| k.as_str())
+        .eq(["banana", "Cherry", "date"]));
+}
+
+#[test]
+fn descending_order_test() {
+    let mut bptree = BPTree::<4, u32, u32, _>::with_comparator(ReverseOrder);
+    for i in 0..300 {
+        bptree.insert((i * 7) % 300, i);
+    }
+
+    assert!(bptree.keys().copied().eq((0..300).rev()));
+    assert_eq!(bptree.first_key_value().map(|(k, _)| *k), Some(299));
+    // ranges run in the tree's order, from the larger key down
+    #[allow(clippy::reversed_empty_ranges)]
+    let range = 20..=15;
+    assert!(bptree.range(range).map(|(k, _)| *k).eq((15..=20).rev()));
+    for i in (0..300).step_by(3) {
+        assert!(bptree.remove(&i).is_some());
+    }
+    assert_eq!(bptree.len(), 200);
+    assert!(bptree
+        .keys()
+        .copied()
+        .eq((0..300).rev().filter(|i| i % 3 != 0)));
+    *bptree.entry(298).or_insert(0) += 1;
+    assert_eq!(bptree.pop_last(), Some((1, 43)));
+}
+
+#[test]
+fn closure_comparator_test() {
+    // order by length first, then alphabetically
+    let by_length = |a: &&str, b: &&str| a.len().cmp(&b.len()).then(a.cmp(b));
+    let mut bptree = BPTree::<3, &str, (), _>::with_comparator(by_length);
+    for word in ["ccc", "a", "bb", "aaa", "b", "dddd", "ab"] {
+        bptree.insert(word, ());
+    }
+
+    assert!(bptree
+        .keys()
+        .copied()
+        .eq(["a", "b", "ab", "bb", "aaa", "ccc", "dddd"]));
+    assert!(bptree.contains_key(&"bb"));
+    assert!(!bptree.contains_key(&"ba"));
+}
+
+#[test]
+fn default_test() {
+    let mut bptree = BPTree::<4, u32, u32, ReverseOrder>::default();
+    assert!(bptree.is_empty());
+    bptree.extend((0..100).map(|i| (i, i)));
+    assert!(bptree.keys().copied().eq((0..100).rev()));
+
+    let bptree: BPTree<3, u32, u32> = Default::default();
+    assert_eq!(bptree.iter().next(), None);
+}
@@ -0,0 +1,269 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use rust_bplus_tree::bp_aggregate::Sum;
+use rust_bplus_tree::bp_comparator::{NaturalOrder, ReverseOrder};
+use rust_bplus_tree::bp_tree::BPTree;
+
+use common::{check_same, Lcg};
+
+fn build<const FANOUT: usize>(keys: impl Iterator<Item = u32>) -> BPTree<FANOUT, u32, u32> {
+    let mut bptree = BPTree::<FANOUT, u32, u32>::new();
+    for key in keys {
+        bptree.insert(key, key * 10);
+    }
+    bptree
+}
+
+#[test]
+fn cursor_walks_both_ways() {
+    let bptree = build::<4>((0..100).map(|i| i * 2));
+    let mut cursor = bptree.cursor();
+    assert_eq!(cursor.key_value(), Some((&0, &0)));
+    let mut forward = vec![*cursor.key().unwrap()];
+    while let Some((key, _)) = cursor.next() {
+        forward.push(*key);
+    }
+    assert!(forward.iter().eq(bptree.keys()));
+    assert_eq!(cursor.key(), None);
+
+    let mut backward = Vec::new();
+    while let Some((key, _)) = cursor.prev() {
+        backward.push(*key);
+    }
+    assert!(backward.iter().eq(bptree.keys().rev()));
+
+    // From the ghost position both ways wrap around.
+    assert_eq!(cursor.next(), Some((&0, &0)));
+    assert_eq!(cursor.prev(), None);
+    assert_eq!(cursor.prev(), Some((&198, &1980)));
+}
+
+#[test]
+fn cursor_seek() {
+    let bptree = build::<5>((0..200).map(|i| i * 3));
+    let expected: BTreeMap<u32, u32> = bptree.iter().map(|(k, v)| (*k, *v)).collect();
+    let mut cursor = bptree.cursor();
+    for probe in 0..610 {
+        cursor.seek(&probe);
+        assert_eq!(cursor.key_value(), expected.range(probe..).next());
+    }
+    cursor.seek_to_last();
+    assert_eq!(cursor.key(), Some(&597));
+    cursor.seek_to_first();
+    assert_eq!(cursor.key(), Some(&0));
+
+    let empty = BPTree::<4, u32, u32>::new();
+    let mut cursor = empty.cursor();
+    assert_eq!(cursor.key(), None);
+    cursor.seek_to_last();
+    assert_eq!(cursor.value(), None);
+    assert_eq!(cursor.next(), None);
+    assert_eq!(cursor.prev(), None);
+}
+
+#[test]
+fn cursor_seek_with_comparator() {
+    let mut bptree = BPTree::<4, u32, u32, ReverseOrder>::default();
+    for key in 0..50 {
+        bptree.insert(key * 2, key);
+    }
+    let mut cursor = bptree.cursor();
+    cursor.seek(&31);
+    assert_eq!(cursor.key(), Some(&30));
+    assert_eq!(cursor.next(), Some((&28, &14)));
+    assert_eq!(cursor.prev(), Some((&30, &15)));
+    assert_eq!(cursor.prev(), Some((&32, &16)));
+}
+
+#[test]
+fn cursor_mut_inserts_around_current() {
+    let mut bptree = build::<4>((0..50).map(|i| i * 10));
+    let mut cursor = bptree.cursor_mut();
+    cursor.seek(&200);
+    cursor.insert_before(195, 1).unwrap();
+    cursor.insert_after(205, 2).unwrap();
+    assert_eq!(cursor.key(), Some(&200));
+    assert_eq!(cursor.prev(), Some((&195, &1)));
+    assert_eq!(cursor.next(), Some((&200, &2000)));
+    assert_eq!(cursor.next(), Some((&205, &2)));
+
+    let err = cursor.insert_after(210, 3).unwrap_err();
+    assert_eq!((err.key, err.value), (210, 3));
+    let err = cursor.insert_before(200, 4).unwrap_err();
+    assert_eq!((err.key, err.value), (200, 4));
+    assert_eq!(cursor.key(), Some(&205));
+
+    // On the ghost position entries go after the last one or before the first.
+    cursor.seek(&1000);
+    assert_eq!(cursor.key(), None);
+    cursor.insert_before(1000, 5).unwrap();
+    cursor.insert_after(0, 6).unwrap_err();
+    assert_eq!(cursor.key(), None);
+    assert_eq!(cursor.prev(), Some((&1000, &5)));
+
+    assert_eq!(bptree.len(), 53);
+    assert_eq!(bptree.first_key_value(), Some((&0, &0)));
+    assert_eq!(bptree.last_key_value(), Some((&1000, &5)));
+}
+
+#[test]
+fn cursor_mut_fills_gaps() {
+    let mut bptree = BPTree::<4, u32, u32>::new();
+    let mut cursor = bptree.cursor_mut();
+    for key in 0..300 {
+        cursor.insert_before(key * 2, key).unwrap();
+    }
+    cursor.seek_to_first();
+    while let Some(key) = cursor.key().copied() {
+        cursor.insert_after(key + 1, key).unwrap();
+        cursor.next();
+        cursor.next();
+    }
+    assert_eq!(bptree.len(), 600);
+    assert!(bptree.keys().copied().eq(0..600));
+}
+
+#[test]
+fn cursor_mut_removes() {
+    let mut bptree = build::<5>(0..400);
+    let mut expected: BTreeMap<u32, u32> = bptree.iter().map(|(k, v)| (*k, *v)).collect();
+    let mut cursor = bptree.cursor_mut();
+    cursor.seek(&17);
+    while let Some(key) = cursor.key().copied() {
+        if key % 3 == 0 {
+            assert_eq!(cursor.remove_current(), expected.remove_entry(&key));
+            assert_eq!(cursor.key(), expected.range(key..).next().map(|(k, _)| k));
+        } else {
+            cursor.next();
+        }
+    }
+    assert_eq!(cursor.remove_current(), None);
+    cursor.seek_to_first();
+    while cursor.remove_current().is_some() {}
+    assert_eq!(bptree.len(), 0);
+    assert_eq!(bptree.iter().next(), None);
+
+    let mut bptree = build::<4>(0..100);
+    let mut cursor = bptree.cursor_mut();
+    cursor.seek_to_last();
+    assert_eq!(cursor.remove_current(), Some((99, 990)));
+    assert_eq!(cursor.key(), None);
+    assert_eq!(cursor.prev(), Some((&98, &980)));
+    assert!(bptree.keys().copied().eq(0..99));
+}
+
+#[test]
+fn cursor_mut_value_mut() {
+    let mut bptree = build::<4>(0..30);
+    let mut cursor = bptree.cursor_mut();
+    while cursor.key().is_some() {
+        *cursor.value_mut().unwrap() += 1;
+        cursor.next();
+    }
+    assert!(bptree.iter().all(|(k, v)| *v == k * 10 + 1));
+}
+
+#[test]
+fn cursor_mut_keeps_aggregates() {
+    let mut bptree = BPTree::<4, u32, u64, NaturalOrder, Sum<u64>>::new();
+    for key in 0..200 {
+        bptree.insert(key * 2, key as u64);
+    }
+    let mut cursor = bptree.cursor_mut();
+    cursor.seek(&100);
+    cursor.insert_after(101, 1000).unwrap();
+    cursor.seek(&300);
+    cursor.remove_current();
+    cursor.remove_current();
+    let expected: u64 = bptree.values().sum();
+    assert_eq!(bptree.aggregate::<u32, _>(..), Sum(expected));
+    assert_eq!(
+        bptree.aggregate(90..=110),
+        Sum(bptree.range(90..=110).map(|(_, v)| *v).sum())
+    );
+}
+
+/// Walks a cursor over the tree making random edits where it stands, and checks
+/// the place of the cursor after each one, and the tree after every round, against
+/// a `BTreeMap`.
+fn check_random_edits<const FANOUT: usize>(seed: u32) {
+    let mut bptree = BPTree::<FANOUT, u32, u64, NaturalOrder, Sum<u64>>::new();
+    let mut expected = BTreeMap::new();
+    for key in 0..200 {
+        bptree.insert(key * 8, u64::from(key));
+        expected.insert(key * 8, u64::from(key));
+    }
+    let mut rng = Lcg(seed);
+    for _ in 0..10 {
+        let mut cursor = bptree.cursor_mut();
+        cursor.seek(&((rng.next() >> 16) % 1700));
+        let mut current = cursor.key().copied();
+        for _ in 0..300 {
+            let before = match current {
+                Some(current) => expected.range(..current).next_back(),
+                None => expected.iter().next_back(),
+            };
+            let before = before.map(|(key, _)| *key);
+            let after = match current {
+                Some(current) => expected.range(current + 1..).next(),
+                None => expected.iter().next(),
+            };
+            let after = after.map(|(key, _)| *key);
+            let seed = rng.next() >> 16;
+            match seed % 5 {
+                0 => {
+                    cursor.next();
+                    current = after;
+                }
+                1 => {
+                    cursor.prev();
+                    current = before;
+                }
+                2 => {
+                    let low = before.map_or(0, |key| key + 1);
+                    let high = current.unwrap_or(low + 8);
+                    if low < high {
+                        let key = low + seed % (high - low);
+                        cursor.insert_before(key, u64::from(seed)).unwrap();
+                        expected.insert(key, u64::from(seed));
+                    }
+                }
+                3 => {
+                    let low = current.map_or(0, |key| key + 1);
+                    let high = after.unwrap_or(low + 8);
+                    if low < high {
+                        let key = low + seed % (high - low);
+                        cursor.insert_after(key, u64::from(seed)).unwrap();
+                        expected.insert(key, u64::from(seed));
+                    }
+                }
+                _ => {
+                    let removed = current.and_then(|key| expected.remove_entry(&key));
+                    assert_eq!(cursor.remove_current(), removed);
+                    if removed.is_some() {
+                        current = after;
+                    }
+                }
+            }
+            assert_eq!(cursor.key().copied(), current);
+        }
+        bptree.validate().unwrap();
+        check_same(&bptree, &expected);
+        for start in (0..1700).step_by(97) {
+            assert_eq!(
+                bptree.aggregate(start..start + 300),
+                Sum(expected.range(start..start + 300).map(|(_, v)| *v).sum())
+            );
+        }
+    }
+}
+
+#[test]
+fn cursor_mut_random_edits() {
+    check_random_edits::<3>(1);
+    check_random_edits::<4>(2);
+    check_random_edits::<5>(3);
+    check_random_edits::<8>(4);
+}
@@ -0,0 +1,150 @@
+use rust_bplus_tree::bp_aggregate::Sum;
+use rust_bplus_tree::bp_comparator::NaturalOrder;
+use rust_bplus_tree::bp_entry::Entry;
+use rust_bplus_tree::bp_tree::BPTree;
+
+#[test]
+fn entry_counting_test() {
+    let mut bptree = BPTree::<3, u32, u32>::new();
+    for i in 0..300 {
+        *bptree.entry(i % 37).or_insert(0) += 1;
+    }
+
+    for i in 0..37 {
+        let expected = if i < 300 % 37 { 9 } else { 8 };
+        assert_eq!(bptree.search(&i), Some(expected));
+    }
+    assert_eq!(bptree.keys().count(), 37);
+}
+
+#[test]
+fn entry_or_insert_with_test() {
+    let mut bptree = BPTree::<4, u32, u32>::new();
+    for i in (0..200).rev() {
+        let value = bptree.entry(i).or_insert_with(|| i * 3);
+        assert_eq!(*value, i * 3);
+        *value += 1;
+    }
+
+    assert!(bptree
+        .iter()
+        .map(|(k, v)| (*k, *v))
+        .eq((0..200).map(|i| (i, i * 3 + 1))));
+    assert_eq!(*bptree.entry(500).or_insert_with_key(|k| k + 1), 501);
+    assert_eq!(*bptree.entry(501).or_default(), 0);
+}
+
+#[test]
+fn entry_and_modify_test() {
+    let mut bptree = BPTree::<3, u32, u32>::new();
+    bptree.insert(1, 10);
+
+    bptree.entry(1).and_modify(|v| *v += 1).or_insert(0);
+    bptree.entry(2).and_modify(|v| *v += 1).or_insert(0);
+    assert_eq!(bptree.search(&1), Some(11));
+    assert_eq!(bptree.search(&2), Some(0));
+}
+
+#[test]
+fn entry_keeps_aggregates_test() {
+    let mut bptree = BPTree::<4, u32, u64, NaturalOrder, Sum<u64>>::new();
+    for i in 0..500 {
+        bptree
+            .entry(i % 97 * 3)
+            .and_modify(|v| *v += u64::from(i))
+            .or_insert_entry(u64::from(i));
+    }
+    let mut entry = bptree.entry(42).insert_entry(7);
+    assert_eq!(entry.modify(|v| std::mem::replace(v, 1000)), 7);
+    assert_eq!(*entry.get(), 1000);
+    // vacant keys landing in full leaves, whose entries move on a split
+    for key in 1000..1100 {
+        if let Entry::Vacant(entry) = bptree.entry(key) {
+            let mut entry = entry.insert_entry(5);
+            assert_eq!(*entry.key(), key);
+            entry.modify(|v| *v += u64::from(key));
+        }
+    }
+
+    bptree.validate().unwrap();
+    assert_eq!(bptree.get(&42), Some(&1000));
+    assert_eq!(bptree.aggregate::<u32, _>(..), Sum(bptree.values().sum()));
+    assert_eq!(
+        bptree.aggregate(10..=200),
+        Sum(bptree.range(10..=200).map(|(_, v)| *v).sum())
+    );
+}
+
+#[test]
+fn occupied_entry_test() {
+    let mut bptree = BPTree::<4, u32, u32>::new();
+    for i in 0..100 {
+        bptree.insert(i, i);
+    }
+
+    match bptree.entry(42) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(*entry.key(), 42);
+            assert_eq!(entry.insert(420), 42);
+            assert_eq!(*entry.get(), 420);
+        }
+        Entry::Vacant(_) => panic!("42 should be occupied"),
+    }
+    match bptree.entry(1000) {
+        Entry::Occupied(_) => panic!("1000 should be vacant"),
+        Entry::Vacant(entry) => assert_eq!(entry.into_key(), 1000),
+    }
+
+    for i in (0..100).step_by(2) {
+        match bptree.entry(i) {
+            Entry::Occupied(entry) => {
+                entry.remove();
+            }
+            Entry::Vacant(_) => panic!("{} should be occupied", i),
+        }
+    }
+    assert!(bptree.keys().copied().eq((1..100).step_by(2)));
+    assert!(bptree.keys().rev().copied().eq((1..100).step_by(2).rev()));
+}
+
+#[test]
+fn first_last_test() {
+    let mut bptree = BPTree::<4, u32, u32>::new();
+    assert_eq!(bptree.first_key_value(), None);
+    assert_eq!(bptree.last_key_value(), None);
+    assert_eq!(bptree.pop_first(), None);
+    assert_eq!(bptree.pop_last(), None);
+
+    for i in (0..100).rev() {
+        bptree.insert(i, i * 2);
+    }
+    assert_eq!(bptree.first_key_value(), Some((&0, &0)));
+    assert_eq!(bptree.last_key_value(), Some((&99, &198)));
+
+    *bptree.first_entry().unwrap().get_mut() += 1;
+    assert_eq!(bptree.get(&0), Some(&1));
+}
+
+#[test]
+fn pop_first_last_test() {
+    let mut bptree = BPTree::<3, u32, u32>::new();
+    for i in 0..100 {
+        bptree.insert(i, i);
+    }
+
+    for i in 0..25 {
+        assert_eq!(bptree.pop_first(), Some((i, i)));
+        assert_eq!(bptree.pop_last(), Some((99 - i, 99 - i)));
+    }
+    assert_eq!(bptree.len(), 50);
+    assert!(bptree.keys().copied().eq(25..75));
+    assert!(bptree.keys().rev().copied().eq((25..75).rev()));
+
+    // drain it like a priority queue
+    let mut drained = Vec::new();
+    while let Some((k, _)) = bptree.pop_first() {
+        drained.push(k);
+    }
+    assert!(drained.into_iter().eq(25..75));
+    assert!(bptree.is_empty());
+}
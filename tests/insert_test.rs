@@ -0,0 +1,48 @@
+use rust_bplus_tree::bp_tree::BPTree;
+
+/// Inserts `keys` in the given order and checks that every one of them can be
+/// found afterwards, which needs the entries handed to a sibling on overflow to
+/// land under the right separator.
+fn insert_and_search<const FANOUT: usize>(keys: &[u32]) {
+    let mut bptree = BPTree::<FANOUT, u32, u32>::new();
+    for &key in keys {
+        bptree.insert(key, key + 1);
+    }
+    for &key in keys {
+        assert_eq!(bptree.search(&key), Some(key + 1));
+    }
+    assert_eq!(bptree.search(&u32::MAX), None);
+}
+
+fn interleaved(n: u32) -> Vec<u32> {
+    // fill the gaps of every leaf after the first pass, so that full leaves keep
+    // handing entries over to their next sibling
+    (0..n).map(|i| i * 2).chain((0..n).rev().map(|i| i * 2 + 1)).collect()
+}
+
+#[test]
+fn insert_ascending_test() {
+    let keys: Vec<u32> = (0..500).collect();
+    insert_and_search::<3>(&keys);
+    insert_and_search::<4>(&keys);
+    insert_and_search::<5>(&keys);
+    insert_and_search::<8>(&keys);
+}
+
+#[test]
+fn insert_descending_test() {
+    let keys: Vec<u32> = (0..500).rev().collect();
+    insert_and_search::<3>(&keys);
+    insert_and_search::<4>(&keys);
+    insert_and_search::<5>(&keys);
+    insert_and_search::<8>(&keys);
+}
+
+#[test]
+fn insert_into_gaps_test() {
+    let keys = interleaved(300);
+    insert_and_search::<3>(&keys);
+    insert_and_search::<4>(&keys);
+    insert_and_search::<5>(&keys);
+    insert_and_search::<8>(&keys);
+}
@@ -0,0 +1,285 @@
+mod common;
+
+use common::Lcg;
+use rust_bplus_tree::bp_tree::BPTree;
+
+fn shuffled(n: u32) -> Vec<u32> {
+    let mut keys: Vec<u32> = (0..n).collect();
+    let mut rng = Lcg(12345);
+    for i in (1..keys.len()).rev() {
+        keys.swap(i, (rng.next() >> 16) as usize % (i + 1));
+    }
+    keys
+}
+
+#[test]
+fn iter_empty_test() {
+    let bptree = BPTree::<3, u32, u32>::new();
+    assert_eq!(bptree.iter().next(), None);
+    assert_eq!(bptree.keys().count(), 0);
+}
+
+#[test]
+fn iter_in_order_test() {
+    let mut bptree = BPTree::<3, u32, u32>::new();
+    for i in shuffled(100) {
+        bptree.insert(i, i * 10);
+    }
+
+    let entries: Vec<(u32, u32)> = bptree.iter().map(|(k, v)| (*k, *v)).collect();
+    let expected: Vec<(u32, u32)> = (0..100).map(|i| (i, i * 10)).collect();
+    assert_eq!(entries, expected);
+}
+
+#[test]
+fn keys_values_test() {
+    let mut bptree = BPTree::<5, u32, u32>::new();
+    for i in shuffled(500) {
+        bptree.insert(i, i + 1);
+    }
+
+    assert!(bptree.keys().copied().eq(0..500));
+    assert!(bptree.values().copied().eq(1..501));
+}
+
+#[test]
+fn iter_larger_fanout_test() {
+    let mut bptree = BPTree::<4, u32, u32>::new();
+    for i in shuffled(2000) {
+        bptree.insert(i % 700, i);
+    }
+
+    assert!(bptree.keys().copied().eq(0..700));
+}
+
+#[test]
+fn range_test() {
+    let mut bptree = BPTree::<4, u32, u32>::new();
+    for i in shuffled(300) {
+        bptree.insert(i * 2, i);
+    }
+
+    assert!(bptree
+        .range(10..20)
+        .map(|(k, _)| *k)
+        .eq((10..20).step_by(2)));
+    assert!(bptree
+        .range(11..=21)
+        .map(|(k, _)| *k)
+        .eq((12..=20).step_by(2)));
+    assert!(bptree.range(..7).map(|(k, _)| *k).eq([0, 2, 4, 6]));
+    assert!(bptree
+        .range(590..)
+        .map(|(k, _)| *k)
+        .eq([590, 592, 594, 596, 598]));
+    assert!(bptree.range(..).map(|(k, _)| *k).eq((0..600).step_by(2)));
+    assert_eq!(bptree.range(1000..).count(), 0);
+    assert_eq!(bptree.range(7..7).count(), 0);
+    assert_eq!(bptree.range(7..=7).count(), 0);
+    assert_eq!(
+        bptree.range(8..=8).map(|(_, v)| *v).collect::<Vec<_>>(),
+        vec![4]
+    );
+}
+
+#[test]
+fn range_excluded_start_test() {
+    use std::ops::Bound::{Excluded, Included, Unbounded};
+
+    let mut bptree = BPTree::<3, u32, u32>::new();
+    for i in shuffled(50) {
+        bptree.insert(i, i);
+    }
+
+    assert!(bptree
+        .range((Excluded(10), Included(15)))
+        .map(|(k, _)| *k)
+        .eq(11..=15));
+    assert!(bptree
+        .range((Excluded(45), Unbounded))
+        .map(|(k, _)| *k)
+        .eq(46..50));
+}
+
+#[test]
+#[should_panic(expected = "range start is greater than range end")]
+fn range_inverted_test() {
+    let bptree = BPTree::<3, u32, u32>::new();
+    #[allow(clippy::reversed_empty_ranges)]
+    let _ = bptree.range(5..3);
+}
+
+#[test]
+fn iter_rev_test() {
+    let mut bptree = BPTree::<4, u32, u32>::new();
+    for i in shuffled(500) {
+        bptree.insert(i, i);
+    }
+
+    assert!(bptree.keys().rev().copied().eq((0..500).rev()));
+    assert!(bptree.values().rev().take(3).copied().eq([499, 498, 497]));
+    assert!(bptree
+        .range(100..200)
+        .rev()
+        .map(|(k, _)| *k)
+        .eq((100..200).rev()));
+}
+
+#[test]
+fn iter_double_ended_test() {
+    let mut bptree = BPTree::<3, u32, u32>::new();
+    for i in shuffled(20) {
+        bptree.insert(i, i);
+    }
+
+    let mut iter = bptree.range(5..=10).map(|(k, _)| *k);
+    assert_eq!(iter.next(), Some(5));
+    assert_eq!(iter.next_back(), Some(10));
+    assert_eq!(iter.next_back(), Some(9));
+    assert_eq!(iter.next(), Some(6));
+    assert_eq!(iter.next(), Some(7));
+    assert_eq!(iter.next_back(), Some(8));
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.next_back(), None);
+}
+
+#[test]
+fn iter_mut_test() {
+    let mut bptree = BPTree::<4, u32, Vec<u32>>::new();
+    for i in shuffled(300) {
+        bptree.insert(i, vec![i]);
+    }
+
+    for (k, v) in bptree.iter_mut() {
+        v.push(*k + 1);
+    }
+    for v in bptree.values_mut().rev().take(100) {
+        v.clear();
+    }
+
+    assert!(bptree
+        .values()
+        .take(200)
+        .enumerate()
+        .all(|(i, v)| *v == [i as u32, i as u32 + 1]));
+    assert!(bptree.values().skip(200).all(|v| v.is_empty()));
+}
+
+#[test]
+fn iter_mut_double_ended_test() {
+    let mut bptree = BPTree::<3, u32, u32>::new();
+    for i in shuffled(30) {
+        bptree.insert(i, i);
+    }
+
+    let mut iter = bptree.iter_mut();
+    let mut seen = Vec::new();
+    while let Some((k, v)) = iter.next() {
+        *v = 0;
+        seen.push(*k);
+        if let Some((k, v)) = iter.next_back() {
+            *v = 1;
+            seen.push(*k);
+        }
+    }
+    seen.sort();
+    assert!(seen.into_iter().eq(0..30));
+    assert!(bptree.values().take(15).all(|v| *v == 0));
+    assert!(bptree.values().skip(15).all(|v| *v == 1));
+}
+
+#[test]
+fn get_mut_test() {
+    let mut bptree = BPTree::<3, u32, Vec<u32>>::new();
+    for i in shuffled(50) {
+        bptree.insert(i, Vec::new());
+    }
+
+    bptree.get_mut(&7).unwrap().extend([1, 2, 3]);
+    assert_eq!(bptree.get_mut(&100), None);
+    assert_eq!(bptree.search(&7), Some(vec![1, 2, 3]));
+}
+
+#[test]
+fn from_iter_test() {
+    let bptree: BPTree<4, u32, u32> = shuffled(500).into_iter().map(|i| (i % 300, i)).collect();
+    assert_eq!(bptree.len(), 300);
+    assert!(bptree.keys().copied().eq(0..300));
+    // a duplicate key keeps the value of its last occurrence
+    let last = shuffled(500)
+        .into_iter()
+        .rev()
+        .find(|i| i % 300 == 7)
+        .unwrap();
+    assert_eq!(bptree[&7], last);
+
+    let empty: BPTree<3, u32, u32> = std::iter::empty().collect();
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn extend_test() {
+    let mut bptree = BPTree::<3, u32, u32>::new();
+    bptree.extend(shuffled(100).into_iter().map(|i| (i, i)));
+    bptree.extend((50..150).map(|i| (i, i + 1)));
+    assert_eq!(bptree.len(), 150);
+    assert_eq!(bptree[&49], 49);
+    assert_eq!(bptree[&50], 51);
+}
+
+#[test]
+fn into_iter_test() {
+    let bptree: BPTree<4, u32, String> = (0..300).map(|i| (i, i.to_string())).collect();
+    let entries: Vec<(u32, String)> = bptree.into_iter().collect();
+    assert!(entries.into_iter().eq((0..300).map(|i| (i, i.to_string()))));
+
+    let bptree: BPTree<3, u32, u32> = (0..50).map(|i| (i, i)).collect();
+    let mut iter = bptree.into_iter();
+    assert_eq!(iter.len(), 50);
+    assert_eq!(iter.next(), Some((0, 0)));
+    assert_eq!(iter.next_back(), Some((49, 49)));
+    assert!(iter
+        .by_ref()
+        .rev()
+        .take(20)
+        .map(|(k, _)| k)
+        .eq((29..49).rev()));
+    assert_eq!(iter.len(), 28);
+    assert!(iter.map(|(k, _)| k).eq(1..29));
+}
+
+#[test]
+fn into_iter_drop_test() {
+    use std::rc::Rc;
+
+    let tracker = Rc::new(());
+    let bptree: BPTree<4, u32, Rc<()>> = (0..100).map(|i| (i, tracker.clone())).collect();
+    assert_eq!(Rc::strong_count(&tracker), 101);
+    let mut iter = bptree.into_iter();
+    iter.next();
+    iter.next_back();
+    assert_eq!(Rc::strong_count(&tracker), 99);
+    drop(iter);
+    assert_eq!(Rc::strong_count(&tracker), 1);
+}
+
+#[test]
+fn ref_into_iter_test() {
+    let mut bptree: BPTree<3, u32, u32> = (0..40).map(|i| (i, i)).collect();
+    for (_, v) in &mut bptree {
+        *v *= 2;
+    }
+    let mut sum = 0;
+    for (k, v) in &bptree {
+        assert_eq!(*v, k * 2);
+        sum += v;
+    }
+    assert_eq!(sum, 1560);
+}
+
+#[test]
+#[should_panic(expected = "no entry found for key")]
+fn index_missing_test() {
+    let bptree: BPTree<3, u32, u32> = (0..10).map(|i| (i, i)).collect();
+    let _ = bptree[&10];
+}
@@ -0,0 +1,67 @@
+use rust_bplus_tree::bp_comparator::ReverseOrder;
+use rust_bplus_tree::bp_multimap::BPMultiMap;
+
+#[test]
+fn multimap_get_all_test() {
+    let mut multimap = BPMultiMap::<3, u32, u32>::new();
+    for row in 0..300 {
+        multimap.insert(row % 7, row);
+    }
+
+    assert_eq!(multimap.len(), 300);
+    for key in 0..7 {
+        // the values of one key span many leaves, and come back in insertion order
+        assert!(multimap
+            .get_all(&key)
+            .copied()
+            .eq((0..300).filter(|row| row % 7 == key)));
+    }
+    assert!(multimap.get_all(&3).rev().take(2).copied().eq([297, 290]));
+    assert_eq!(multimap.get_all(&7).count(), 0);
+    assert!(multimap.contains_key(&6));
+    assert!(!multimap.contains_key(&7));
+    assert!(multimap.iter().map(|(k, _)| *k).is_sorted());
+}
+
+#[test]
+fn multimap_remove_test() {
+    let mut multimap = BPMultiMap::<4, u32, u32>::new();
+    for row in 0..500 {
+        multimap.insert(row % 5, row);
+    }
+
+    assert_eq!(multimap.remove_one(&2), Some(2));
+    assert_eq!(multimap.remove_one(&2), Some(7));
+    assert_eq!(multimap.get_all(&2).next(), Some(&12));
+    assert_eq!(multimap.len(), 498);
+
+    let removed = multimap.remove_all(&3);
+    assert!(removed.into_iter().eq((0..500).filter(|row| row % 5 == 3)));
+    assert_eq!(multimap.get_all(&3).count(), 0);
+    assert_eq!(multimap.remove_one(&3), None);
+    assert!(multimap.remove_all(&3).is_empty());
+    assert_eq!(multimap.len(), 398);
+
+    // the entries around the removed ones are still found through the index
+    assert_eq!(multimap.get_all(&2).count(), 98);
+    assert_eq!(multimap.get_all(&4).count(), 100);
+    for key in [0, 1, 2, 4] {
+        multimap.remove_all(&key);
+    }
+    assert!(multimap.is_empty());
+
+    multimap.insert(1, 1);
+    multimap.insert(1, 2);
+    assert!(multimap.iter().eq([(&1, &1), (&1, &2)]));
+}
+
+#[test]
+fn multimap_comparator_test() {
+    let mut multimap = BPMultiMap::<3, u32, char, _>::with_comparator(ReverseOrder);
+    for (i, c) in "abcdefghij".chars().enumerate() {
+        multimap.insert(i as u32 % 3, c);
+    }
+
+    assert!(multimap.iter().map(|(_, c)| *c).eq("cfibehadgj".chars()));
+    assert!(multimap.get_all(&0).copied().eq("adgj".chars()));
+}
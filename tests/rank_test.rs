@@ -0,0 +1,163 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::ops::Bound::{Excluded, Included};
+use std::time::Instant;
+
+use common::{check_same, Lcg};
+
+use rust_bplus_tree::bp_tree::BPTree;
+
+/// Checks `nth`, `rank` and `count_range` on every position and key, and a few
+/// keys in between.
+fn check_counts<const FANOUT: usize>(
+    bptree: &BPTree<FANOUT, u32, u32>,
+    expected: &BTreeMap<u32, u32>,
+) {
+    check_same(bptree, expected);
+    for (i, entry) in expected.iter().enumerate() {
+        assert_eq!(bptree.nth(i), Some(entry));
+    }
+    assert_eq!(bptree.nth(expected.len()), None);
+    let max = expected.keys().last().map_or(0, |key| key + 2);
+    for key in 0..max {
+        assert_eq!(bptree.rank(&key), expected.range(..key).count());
+    }
+    for start in (0..max).step_by(23) {
+        for end in (start..max + 3).step_by(17) {
+            assert_eq!(
+                bptree.count_range(start..end),
+                expected.range(start..end).count()
+            );
+            assert_eq!(
+                bptree.count_range(start..=end),
+                expected.range(start..=end).count()
+            );
+            assert_eq!(
+                bptree.count_range((Excluded(start), Included(end))),
+                expected.range((Excluded(start), Included(end))).count()
+            );
+        }
+        assert_eq!(bptree.count_range(start..), expected.range(start..).count());
+        assert_eq!(bptree.count_range(..start), expected.range(..start).count());
+    }
+    assert_eq!(bptree.count_range::<u32, _>(..), expected.len());
+}
+
+fn check_random<const FANOUT: usize>(n: u32) {
+    let mut bptree = BPTree::<FANOUT, u32, u32>::new();
+    let mut expected = BTreeMap::new();
+    let mut rng = Lcg(n);
+    for round in 0..4 {
+        for _ in 0..n {
+            let seed = rng.next();
+            let key = (seed >> 16) % (n * 2);
+            // removes take over in the later rounds
+            if seed % 4 >= round {
+                assert_eq!(bptree.insert(key, seed), expected.insert(key, seed));
+            } else {
+                assert_eq!(bptree.remove(&key), expected.remove(&key));
+            }
+        }
+        check_counts(&bptree, &expected);
+    }
+
+    while let Some(entry) = bptree.pop_first() {
+        assert_eq!(Some(entry), expected.pop_first());
+        if expected.len() % 47 == 0 {
+            check_counts(&bptree, &expected);
+        }
+    }
+}
+
+#[test]
+fn rank_random_test() {
+    for n in [1, 2, 10, 100, 300] {
+        check_random::<3>(n);
+        check_random::<4>(n);
+        check_random::<5>(n);
+        check_random::<8>(n);
+    }
+}
+
+fn check_bulk_operations<const FANOUT: usize>() {
+    let entries = (0..400).map(|i| (i * 2, i));
+    let mut bptree = BPTree::<FANOUT, u32, u32>::from_sorted_iter(entries.clone(), 0.7).unwrap();
+    let mut expected: BTreeMap<u32, u32> = entries.collect();
+    check_counts(&bptree, &expected);
+    check_counts(&bptree.clone(), &expected);
+
+    *bptree.entry(33).or_insert(0) += 1;
+    *expected.entry(33).or_insert(0) += 1;
+    check_counts(&bptree, &expected);
+
+    let mut right = bptree.split_off(&301);
+    let mut expected_right = expected.split_off(&301);
+    check_counts(&bptree, &expected);
+    check_counts(&right, &expected_right);
+
+    assert_eq!(right.remove_range(500..600), 50);
+    expected_right.retain(|key, _| !(500..600).contains(key));
+    check_counts(&right, &expected_right);
+
+    bptree.append(&mut right);
+    expected.append(&mut expected_right);
+    check_counts(&bptree, &expected);
+
+    bptree.retain(|key, _| key % 3 != 0);
+    expected.retain(|key, _| key % 3 != 0);
+    check_counts(&bptree, &expected);
+
+    let extracted: Vec<_> = bptree.extract_if(|key, _| key % 5 == 0).take(20).collect();
+    for (key, _) in extracted {
+        expected.remove(&key);
+    }
+    check_counts(&bptree, &expected);
+}
+
+#[test]
+fn rank_bulk_operations_test() {
+    check_bulk_operations::<3>();
+    check_bulk_operations::<4>();
+    check_bulk_operations::<5>();
+    check_bulk_operations::<8>();
+}
+
+#[test]
+fn percentile_test() {
+    let bptree: BPTree<6, u32, ()> = (1..=1000).map(|i| (i * 10, ())).collect();
+    let p95 = bptree.nth(bptree.len() * 95 / 100).map(|(key, _)| *key);
+    assert_eq!(p95, Some(9510));
+    assert_eq!(bptree.rank(&9510), 950);
+    assert_eq!(bptree.rank(&9515), 951);
+    assert_eq!(bptree.rank(&0), 0);
+    assert_eq!(bptree.count_range(9505..9600), 9);
+    #[allow(clippy::reversed_empty_ranges)]
+    let inverted = 600..500;
+    assert_eq!(bptree.count_range(inverted), 0);
+}
+
+/// Splitting must not walk the entries to count them: on a large tree it has to
+/// be far cheaper than a single pass over them. This times the two, so it only
+/// runs on request, best in a release build.
+#[test]
+#[ignore = "benchmark, run with --release -- --ignored"]
+fn split_off_large_tree_bench() {
+    let n = 1 << 20;
+    let mut bptree = BPTree::<32, u32, u32>::from_sorted_iter((0..n).map(|i| (i, i)), 1.0).unwrap();
+    let start = Instant::now();
+    assert_eq!(bptree.iter().count(), n as usize);
+    let scan = start.elapsed();
+
+    let start = Instant::now();
+    let right = bptree.split_off(&(n / 2 + 7));
+    let split = start.elapsed();
+    assert_eq!(bptree.len(), n as usize / 2 + 7);
+    assert_eq!(right.len(), n as usize / 2 - 7);
+    assert!(
+        split * 20 < scan,
+        "split_off took {:?}, a scan takes {:?}",
+        split,
+        scan
+    );
+}
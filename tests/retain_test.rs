@@ -0,0 +1,197 @@
+mod common;
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+
+use common::{check_same, Lcg};
+
+use rust_bplus_tree::bp_aggregate::Aggregate;
+use rust_bplus_tree::bp_comparator::NaturalOrder;
+use rust_bplus_tree::bp_tree::BPTree;
+
+fn build<const FANOUT: usize>(n: u32) -> (BPTree<FANOUT, u32, u32>, BTreeMap<u32, u32>) {
+    let mut bptree = BPTree::<FANOUT, u32, u32>::new();
+    let mut expected = BTreeMap::new();
+    for i in 0..n {
+        let key = i * 7 % n;
+        bptree.insert(key, i);
+        expected.insert(key, i);
+    }
+    (bptree, expected)
+}
+
+/// Runs random inserts and removes against both, to make sure the tree is still
+/// well formed.
+fn churn<const FANOUT: usize>(
+    bptree: &mut BPTree<FANOUT, u32, u32>,
+    expected: &mut BTreeMap<u32, u32>,
+    n: u32,
+) {
+    let mut rng = Lcg(n);
+    for _ in 0..n * 2 {
+        let seed = rng.next();
+        let key = (seed >> 16) % (n + 10);
+        if seed & 1 == 0 {
+            assert_eq!(bptree.insert(key, seed), expected.insert(key, seed));
+        } else {
+            assert_eq!(bptree.remove(&key), expected.remove(&key));
+        }
+    }
+    check_same(bptree, expected);
+}
+
+fn check_retain<const FANOUT: usize>(n: u32) {
+    let predicates: [fn(&u32) -> bool; 5] = [
+        |_| true,
+        |_| false,
+        |key| key % 2 == 0,
+        |key| key % 5 == 1,
+        |key| (10..50).contains(key),
+    ];
+    for keep in predicates {
+        let (mut bptree, mut expected) = build::<FANOUT>(n);
+        bptree.retain(|key, value| {
+            *value += 1;
+            keep(key)
+        });
+        expected.retain(|key, value| {
+            *value += 1;
+            keep(key)
+        });
+        check_same(&bptree, &expected);
+        churn(&mut bptree, &mut expected, n);
+    }
+}
+
+#[test]
+fn retain_test() {
+    for n in [0, 1, 2, 5, 17, 100, 1000] {
+        check_retain::<3>(n);
+        check_retain::<4>(n);
+        check_retain::<5>(n);
+        check_retain::<8>(n);
+    }
+}
+
+fn check_extract_if<const FANOUT: usize>(n: u32) {
+    for take in [0, 1, 3, n as usize / 2, n as usize] {
+        let (mut bptree, mut expected) = build::<FANOUT>(n);
+        let extracted: Vec<(u32, u32)> = bptree
+            .extract_if(|key, _| key % 3 != 0)
+            .take(take)
+            .collect();
+        let expected_extracted: Vec<(u32, u32)> = expected
+            .iter()
+            .filter(|(key, _)| *key % 3 != 0)
+            .take(take)
+            .map(|(key, value)| (*key, *value))
+            .collect();
+        for (key, _) in &expected_extracted {
+            expected.remove(key);
+        }
+        assert_eq!(extracted, expected_extracted);
+        check_same(&bptree, &expected);
+        churn(&mut bptree, &mut expected, n);
+    }
+}
+
+#[test]
+fn extract_if_test() {
+    for n in [1, 2, 5, 17, 100, 1000] {
+        check_extract_if::<3>(n);
+        check_extract_if::<4>(n);
+        check_extract_if::<5>(n);
+        check_extract_if::<8>(n);
+    }
+}
+
+#[test]
+fn extract_if_all_test() {
+    let (mut bptree, _) = build::<4>(200);
+    let mut extract = bptree.extract_if(|_, _| true);
+    assert_eq!(extract.size_hint(), (0, Some(200)));
+    assert!(extract.by_ref().map(|(key, _)| key).eq(0..200));
+    assert_eq!(extract.next(), None);
+    drop(extract);
+    assert!(bptree.is_empty());
+    assert_eq!(bptree.first_key_value(), None);
+    bptree.insert(1, 1);
+    assert_eq!(bptree.get(&1), Some(&1));
+}
+
+#[test]
+fn extract_if_forget_test() {
+    let (mut bptree, _) = build::<4>(200);
+    let mut extract = bptree.extract_if(|key, _| key % 4 != 0);
+    assert_eq!(extract.by_ref().take(100).count(), 100);
+    std::mem::forget(extract);
+
+    // the leaked entries are lost, but the tree is still well formed
+    bptree.validate().unwrap();
+    assert_eq!(bptree.len(), 0);
+    assert_eq!(bptree.nth(0), None);
+    assert_eq!(bptree.remove(&0), None);
+    for key in 0..100 {
+        bptree.insert(key, key);
+    }
+    bptree.validate().unwrap();
+    assert_eq!(bptree.remove(&50), Some(50));
+    assert!(bptree.keys().copied().eq((0..100).filter(|&key| key != 50)));
+}
+
+thread_local! {
+    static SUMMARIZED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A sum that counts how many entries it was asked to summarize.
+#[derive(Debug, Clone, PartialEq)]
+struct CountingSum(u64);
+
+impl Aggregate<u32, u32> for CountingSum {
+    fn identity() -> Self {
+        CountingSum(0)
+    }
+
+    fn from_entry(_: &u32, value: &u32) -> Self {
+        SUMMARIZED.with(|count| count.set(count.get() + 1));
+        CountingSum(u64::from(*value))
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        CountingSum(self.0 + other.0)
+    }
+}
+
+#[test]
+fn extract_if_repairs_only_what_it_visited_test() {
+    let n = 100_000;
+    let mut bptree = BPTree::<8, u32, u32, NaturalOrder, CountingSum>::from_sorted_iter(
+        (0..n).map(|i| (i, i)),
+        1.0,
+    )
+    .unwrap();
+    for start in [0, n / 2, n - 1] {
+        SUMMARIZED.with(|count| count.set(0));
+        let mut visited = 0;
+        let extracted: Vec<_> = bptree
+            .extract_if(|key, _| {
+                visited += 1;
+                *key >= start
+            })
+            .take(1)
+            .collect();
+        assert_eq!(extracted, vec![(start, start)]);
+        // only the leaves it went through are summarized again, not the rest
+        let summarized = SUMMARIZED.with(Cell::get);
+        assert!(
+            summarized < visited + 100,
+            "summarized {} entries after visiting {}",
+            summarized,
+            visited
+        );
+    }
+    bptree.validate().unwrap();
+    assert_eq!(bptree.len(), n as usize - 3);
+    let expected: u64 = bptree.values().map(|value| u64::from(*value)).sum();
+    assert_eq!(bptree.aggregate::<u32, _>(..), CountingSum(expected));
+}
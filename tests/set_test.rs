@@ -0,0 +1,102 @@
+use std::collections::BTreeSet;
+
+use rust_bplus_tree::bp_comparator::ReverseOrder;
+use rust_bplus_tree::bp_set::BPTreeSet;
+
+fn set_of(values: impl IntoIterator<Item = u32>) -> BPTreeSet<4, u32> {
+    let mut set = BPTreeSet::new();
+    for value in values {
+        set.insert(value);
+    }
+    set
+}
+
+#[test]
+fn set_basic_test() {
+    let mut set = BPTreeSet::<3, u32>::new();
+    assert!(set.is_empty());
+    for i in 0..100 {
+        assert!(set.insert((i * 37) % 100));
+    }
+    assert!(!set.insert(42));
+    assert_eq!(set.len(), 100);
+    assert!(set.contains(&42));
+    assert!(!set.contains(&100));
+    assert!(set.iter().copied().eq(0..100));
+    assert!(set.range(10..15).copied().eq(10..15));
+    assert!(set.range(..=3).rev().copied().eq([3, 2, 1, 0]));
+
+    for i in (0..100).step_by(2) {
+        assert!(set.remove(&i));
+    }
+    assert!(!set.remove(&0));
+    assert_eq!(set.len(), 50);
+    assert_eq!(set.first(), Some(&1));
+    assert_eq!(set.pop_last(), Some(99));
+    assert!(set.iter().copied().eq((1..99).step_by(2)));
+
+    set.clear();
+    assert!(set.is_empty());
+    assert_eq!(set.first(), None);
+}
+
+#[test]
+fn set_algebra_test() {
+    let a = set_of((0..300).filter(|i| i % 2 == 0));
+    let b = set_of((0..300).filter(|i| i % 3 == 0));
+    let a_std: BTreeSet<u32> = a.iter().copied().collect();
+    let b_std: BTreeSet<u32> = b.iter().copied().collect();
+
+    assert!(a.union(&b).copied().eq(a_std.union(&b_std).copied()));
+    assert!(a
+        .intersection(&b)
+        .copied()
+        .eq(a_std.intersection(&b_std).copied()));
+    assert!(a
+        .difference(&b)
+        .copied()
+        .eq(a_std.difference(&b_std).copied()));
+    assert!(b
+        .difference(&a)
+        .copied()
+        .eq(b_std.difference(&a_std).copied()));
+    assert!(a
+        .symmetric_difference(&b)
+        .copied()
+        .eq(a_std.symmetric_difference(&b_std).copied()));
+}
+
+#[test]
+fn set_algebra_edge_test() {
+    let empty = set_of([]);
+    let a = set_of(0..10);
+    let b = set_of(5..20);
+
+    assert!(a.union(&empty).copied().eq(0..10));
+    assert_eq!(a.intersection(&empty).count(), 0);
+    assert!(a.difference(&empty).copied().eq(0..10));
+    assert_eq!(empty.difference(&a).count(), 0);
+    assert!(empty.symmetric_difference(&a).copied().eq(0..10));
+
+    assert!(a.union(&b).copied().eq(0..20));
+    assert!(a.intersection(&b).copied().eq(5..10));
+    assert!(b.difference(&a).copied().eq(10..20));
+    assert!(a.symmetric_difference(&b).copied().eq((0..5).chain(10..20)));
+    assert_eq!(a.intersection(&a).count(), 10);
+    assert_eq!(a.difference(&a).count(), 0);
+}
+
+#[test]
+fn set_comparator_test() {
+    let mut a = BPTreeSet::<3, u32, _>::with_comparator(ReverseOrder);
+    let mut b = BPTreeSet::<3, u32, _>::with_comparator(ReverseOrder);
+    for i in 0..20 {
+        a.insert(i);
+        b.insert(i + 10);
+    }
+
+    assert!(a.iter().copied().eq((0..20).rev()));
+    assert!(a.union(&b).copied().eq((0..30).rev()));
+    assert!(a.intersection(&b).copied().eq((10..20).rev()));
+    assert!(a.difference(&b).copied().eq((0..10).rev()));
+}
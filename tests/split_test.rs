@@ -0,0 +1,203 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use common::{check_same, Lcg};
+
+use rust_bplus_tree::bp_tree::BPTree;
+
+fn build<const FANOUT: usize>(
+    keys: impl Iterator<Item = u32>,
+) -> (BPTree<FANOUT, u32, u32>, BTreeMap<u32, u32>) {
+    let mut bptree = BPTree::<FANOUT, u32, u32>::new();
+    let mut expected = BTreeMap::new();
+    for key in keys {
+        bptree.insert(key, key * 10);
+        expected.insert(key, key * 10);
+    }
+    (bptree, expected)
+}
+
+fn check_valid<const FANOUT: usize>(
+    bptree: &BPTree<FANOUT, u32, u32>,
+    expected: &BTreeMap<u32, u32>,
+) {
+    bptree.validate().unwrap();
+    check_same(bptree, expected);
+}
+
+/// Runs random inserts and removes against both, to make sure the tree is still
+/// well formed.
+fn churn<const FANOUT: usize>(
+    bptree: &mut BPTree<FANOUT, u32, u32>,
+    expected: &mut BTreeMap<u32, u32>,
+    seed: u32,
+) {
+    let mut rng = Lcg(seed);
+    for _ in 0..300 {
+        let seed = rng.next();
+        let key = (seed >> 16) % 250;
+        if seed & 1 == 0 {
+            assert_eq!(bptree.insert(key, seed), expected.insert(key, seed));
+        } else {
+            assert_eq!(bptree.remove(&key), expected.remove(&key));
+        }
+    }
+    check_valid(bptree, expected);
+}
+
+fn check_split_off<const FANOUT: usize>(n: u32) {
+    for at in [0, 1, n / 3, n / 2 + 1, n - 1, n, n + 5] {
+        let (mut left, mut expected_left) = build::<FANOUT>((0..n).map(|i| i * 2));
+        let mut right = left.split_off(&at);
+        let mut expected_right = expected_left.split_off(&at);
+        check_valid(&left, &expected_left);
+        check_valid(&right, &expected_right);
+        churn(&mut left, &mut expected_left, at);
+        churn(&mut right, &mut expected_right, at + 1);
+    }
+}
+
+#[test]
+fn split_off_test() {
+    for n in [1, 2, 5, 17, 100, 500] {
+        check_split_off::<3>(n);
+        check_split_off::<4>(n);
+        check_split_off::<5>(n);
+        check_split_off::<8>(n);
+    }
+}
+
+#[test]
+fn split_off_empty_test() {
+    let mut bptree = BPTree::<4, u32, u32>::new();
+    let other = bptree.split_off(&3);
+    assert!(bptree.is_empty());
+    assert!(other.is_empty());
+}
+
+fn check_append<const FANOUT: usize>(a: (u32, u32), b: (u32, u32)) {
+    let (mut left, mut expected_left) = build::<FANOUT>(a.0..a.1);
+    let (mut right, mut expected_right) = build::<FANOUT>(b.0..b.1);
+    for (key, value) in right.iter_mut() {
+        *value = key + 1;
+    }
+    for (key, value) in expected_right.iter_mut() {
+        *value = key + 1;
+    }
+    left.append(&mut right);
+    expected_left.append(&mut expected_right);
+    check_valid(&left, &expected_left);
+    assert!(right.is_empty());
+    assert_eq!(right.iter().next(), None);
+    churn(&mut left, &mut expected_left, a.1);
+    churn(&mut right, &mut expected_right, b.1);
+}
+
+#[test]
+fn append_test() {
+    let cases = [
+        // disjoint, either way round, with different heights
+        ((0, 100), (100, 103)),
+        ((0, 3), (3, 200)),
+        ((50, 60), (0, 50)),
+        ((10, 200), (0, 2)),
+        ((0, 40), (40, 80)),
+        // overlapping
+        ((0, 100), (50, 150)),
+        ((20, 30), (0, 100)),
+        // either side empty
+        ((0, 0), (0, 50)),
+        ((0, 50), (0, 0)),
+    ];
+    for (a, b) in cases {
+        check_append::<3>(a, b);
+        check_append::<4>(a, b);
+        check_append::<5>(a, b);
+        check_append::<8>(a, b);
+    }
+}
+
+#[test]
+fn split_off_then_append_test() {
+    let (mut bptree, expected) = build::<5>(0..1000);
+    let mut tail = bptree.split_off(&617);
+    let mut middle = bptree.split_off(&200);
+    middle.append(&mut tail);
+    bptree.append(&mut middle);
+    check_valid(&bptree, &expected);
+}
+
+fn check_remove_range<const FANOUT: usize>(n: u32) {
+    use std::ops::Bound::{self, Excluded, Included, Unbounded};
+
+    let points = [0, 1, n / 4, n / 2 + 1, n - 1, n, n * 2 + 3];
+    for start in points {
+        for end in points {
+            let bounds: [(Bound<u32>, Bound<u32>); 5] = [
+                (Included(start), Excluded(end)),
+                (Included(start), Included(end)),
+                (Excluded(start), Included(end)),
+                (Unbounded, Excluded(end)),
+                (Excluded(start), Unbounded),
+            ];
+            for range in bounds {
+                let (mut bptree, mut expected) = build::<FANOUT>((0..n).map(|i| i * 2));
+                let before = expected.len();
+                expected.retain(|key, _| !std::ops::RangeBounds::contains(&range, key));
+                assert_eq!(bptree.remove_range(range), before - expected.len());
+                check_valid(&bptree, &expected);
+                churn(&mut bptree, &mut expected, start ^ end);
+            }
+        }
+    }
+}
+
+#[test]
+fn remove_range_test() {
+    for n in [1, 2, 5, 17, 100, 300] {
+        check_remove_range::<3>(n);
+        check_remove_range::<4>(n);
+        check_remove_range::<5>(n);
+        check_remove_range::<8>(n);
+    }
+}
+
+#[test]
+fn remove_range_all_test() {
+    let (mut bptree, _) = build::<4>(0..100);
+    assert_eq!(bptree.remove_range(..), 100);
+    assert!(bptree.is_empty());
+    assert_eq!(bptree.remove_range(3..10), 0);
+    bptree.insert(5, 50);
+    assert_eq!(bptree.get(&5), Some(&50));
+}
+
+/// Removing a few keys from the middle of a large tree must only cut and join
+/// along two paths, so it has to be far cheaper than a single pass over the
+/// entries. This times the two, so it only runs on request, best in a release
+/// build.
+#[test]
+#[ignore = "benchmark, run with --release -- --ignored"]
+fn remove_range_large_tree_bench() {
+    let n = 1 << 20;
+    let mut bptree = BPTree::<32, u32, u32>::from_sorted_iter((0..n).map(|i| (i, i)), 1.0).unwrap();
+    let start = Instant::now();
+    assert_eq!(bptree.iter().count(), n as usize);
+    let scan = start.elapsed();
+
+    let start = Instant::now();
+    assert_eq!(bptree.remove_range(n / 2..n / 2 + 10), 10);
+    let remove = start.elapsed();
+    assert_eq!(bptree.len(), n as usize - 10);
+    assert_eq!(bptree.get(&(n / 2 - 1)), Some(&(n / 2 - 1)));
+    assert_eq!(bptree.get(&(n / 2)), None);
+    assert_eq!(bptree.get(&(n / 2 + 10)), Some(&(n / 2 + 10)));
+    assert!(
+        remove * 20 < scan,
+        "remove_range took {:?}, a scan takes {:?}",
+        remove,
+        scan
+    );
+}
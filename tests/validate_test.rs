@@ -0,0 +1,120 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use common::{check_same, Lcg};
+use rust_bplus_tree::bp_aggregate::Sum;
+use rust_bplus_tree::bp_comparator::{NaturalOrder, ReverseOrder};
+use rust_bplus_tree::bp_tree::{BPTree, InvariantViolation, ViolationKind};
+
+fn churn<const FANOUT: usize>(seed: u32) {
+    let mut rng = Lcg(seed);
+    let mut bptree = BPTree::<FANOUT, u32, u32>::new();
+    let mut expected = BTreeMap::new();
+    bptree.validate().unwrap();
+    for _ in 0..2000 {
+        let key = (rng.next() >> 16) % 300;
+        if (rng.next() >> 16).is_multiple_of(3) {
+            assert_eq!(bptree.remove(&key), expected.remove(&key));
+        } else {
+            assert_eq!(bptree.insert(key, key), expected.insert(key, key));
+        }
+        bptree.validate().unwrap();
+    }
+    check_same(&bptree, &expected);
+    while bptree.pop_first().is_some() {
+        bptree.validate().unwrap();
+    }
+}
+
+#[test]
+fn validate_after_inserts_and_removes() {
+    churn::<3>(1);
+    churn::<4>(2);
+    churn::<5>(3);
+    churn::<8>(4);
+}
+
+#[test]
+fn validate_after_bulk_operations() {
+    for fill_factor in [0.5, 0.7, 1.0] {
+        let bptree =
+            BPTree::<5, u32, u32>::from_sorted_iter((0..500).map(|i| (i, i)), fill_factor).unwrap();
+        bptree.validate().unwrap();
+        bptree.clone().validate().unwrap();
+    }
+    let mut bptree: BPTree<4, u32, u32> = (0..400).map(|i| (i, i)).collect();
+    bptree.validate().unwrap();
+
+    for at in [0, 1, 57, 200, 399, 400] {
+        let mut left = bptree.clone();
+        let mut right = left.split_off(&at);
+        left.validate().unwrap();
+        right.validate().unwrap();
+        left.append(&mut right);
+        left.validate().unwrap();
+        assert_eq!(left.len(), 400);
+    }
+
+    bptree.remove_range(100..250);
+    bptree.validate().unwrap();
+    bptree.retain(|k, _| k % 3 != 0);
+    bptree.validate().unwrap();
+    bptree.extract_if(|k, _| k % 5 == 0).for_each(drop);
+    bptree.validate().unwrap();
+    let mut other: BPTree<4, u32, u32> = (150..600).map(|i| (i, i)).collect();
+    bptree.append(&mut other);
+    bptree.validate().unwrap();
+}
+
+#[test]
+fn validate_after_cursor_edits() {
+    let mut bptree = BPTree::<4, u32, u64, NaturalOrder, Sum<u64>>::new();
+    let mut cursor = bptree.cursor_mut();
+    for key in 0..200 {
+        cursor.insert_before(key * 2, key as u64).unwrap();
+    }
+    cursor.seek(&50);
+    for _ in 0..60 {
+        cursor.remove_current();
+    }
+    bptree.validate().unwrap();
+}
+
+#[test]
+fn validate_with_comparator() {
+    let mut bptree = BPTree::<4, u32, u32, ReverseOrder>::default();
+    for key in 0..300 {
+        bptree.insert(key * 7 % 301, key);
+    }
+    bptree.validate().unwrap();
+    bptree.remove_range(50..150);
+    bptree.validate().unwrap();
+}
+
+#[test]
+fn violation_names_the_node() {
+    let violation = InvariantViolation {
+        path: vec![2, 0],
+        kind: ViolationKind::Occupancy {
+            len: 1,
+            min: 2,
+            max: 4,
+        },
+    };
+    assert_eq!(
+        violation.to_string(),
+        "node root/2/0 holds 1, outside of 2..=4"
+    );
+    let violation = InvariantViolation {
+        path: Vec::new(),
+        kind: ViolationKind::Length {
+            cached: 3,
+            actual: 2,
+        },
+    };
+    assert_eq!(
+        violation.to_string(),
+        "node root is in a tree of length 3 with 2 entries"
+    );
+}